@@ -0,0 +1,16 @@
+use driverstation::{console::Message, Robot};
+
+#[tokio::main]
+async fn main() {
+    let robot = Robot::new(8891);
+    let mut console = robot.console();
+
+    while let Some(message) = console.recv().await {
+        match message {
+            Message::Output { message, .. } => println!("{message}"),
+            Message::Error {
+                details, location, ..
+            } => eprintln!("{details} ({location})"),
+        }
+    }
+}
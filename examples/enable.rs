@@ -3,11 +3,11 @@ use std::time::Duration;
 use driverstation::Robot;
 
 fn main() {
-    let mut robot = Robot::new(8891);
+    let robot = Robot::new(8891);
 
     let mut enabled = false;
     loop {
-        robot.set_enabled(enabled);
+        robot.set_enabled(enabled).unwrap();
         enabled = !enabled;
 
         println!("{}", robot.enabled());
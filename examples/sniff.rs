@@ -0,0 +1,45 @@
+//! Decodes the control packets in a capture of the official driver station,
+//! and checks this crate writes each of them back out byte for byte.
+//!
+//! `cargo run --example sniff --features pcap -- capture.pcapng`
+
+use driverstation::{pcap, send::udp::Packet, traits::Bytes};
+
+const ROBOT_UDP_PORT: u16 = 1110;
+
+fn main() {
+    let path = std::env::args()
+        .nth(1)
+        .expect("usage: sniff <capture.pcapng>");
+    let capture = std::fs::read(&path).expect("unable to read capture");
+    let segments = pcap::read(&capture).expect("unable to parse capture");
+
+    let mut packets = 0;
+    let mut mismatches = 0;
+    for segment in segments
+        .iter()
+        .filter(|segment| segment.is_udp() && segment.dst.port() == ROBOT_UDP_PORT)
+    {
+        packets += 1;
+
+        let packet = match Packet::try_from(segment.payload.as_slice()) {
+            Ok(packet) => packet,
+            Err(err) => {
+                mismatches += 1;
+                println!("{err:?} parsing {:02x?}", segment.payload);
+                continue;
+            }
+        };
+        println!("{packet:?}");
+
+        let mut out = Vec::new();
+        packet.write_bytes(&mut out);
+        if out != segment.payload {
+            mismatches += 1;
+            println!("  sent:    {:02x?}", segment.payload);
+            println!("  written: {out:02x?}");
+        }
+    }
+
+    println!("{packets} packets, {mismatches} differed");
+}
@@ -2,10 +2,9 @@ use std::collections::HashMap;
 
 use proc_macro::TokenStream;
 use proc_macro2::Span;
-use quote::{quote, ToTokens, TokenStreamExt};
+use quote::{quote, ToTokens};
 use syn::{
-    parse::Parse, punctuated::Punctuated, spanned::Spanned, Data, DeriveInput, ExprClosure, Ident,
-    LitByteStr, Meta, Path, PathArguments, Token,
+    parse::Parse, spanned::Spanned, Data, DeriveInput, Ident, LitByteStr, Meta, Path, Token,
 };
 
 enum Fields {
@@ -26,7 +25,7 @@ struct Attr {
 }
 
 impl Parse for Attr {
-    fn parse(mut input: syn::parse::ParseStream) -> syn::Result<Self> {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
         let indicator: LitByteStr = input.parse()?;
         let callback = if input.peek(Token![,]) {
             let _: Token![,] = input.parse()?;
@@ -50,6 +49,7 @@ impl Parse for Attr {
 
 enum Callback {
     Label(Path),
+    #[allow(dead_code)]
     Inline {
         i_ident: Ident,
         bytes_ident: Ident,
@@ -171,7 +171,7 @@ pub fn parse_entries(input: TokenStream) -> TokenStream {
         };
 
         let s = attr.indicator.span();
-        if let Some(_) = entries.insert(attr.indicator, entry) {
+        if entries.insert(attr.indicator, entry).is_some() {
             return syn::Error::new(s, "duplicate indicator found")
                 .into_compile_error()
                 .into();
@@ -179,7 +179,7 @@ pub fn parse_entries(input: TokenStream) -> TokenStream {
     }
 
     let mut key_pairs: Vec<(LitByteStr, Entry)> = entries.into_iter().collect();
-    key_pairs.sort_by(|(a, _), (b, _)| a.value().len().cmp(&b.value().len()));
+    key_pairs.sort_by_key(|(indicator, _)| indicator.value().len());
 
     let mut parse = proc_macro2::TokenStream::new();
     for (i, (indicator, entry)) in key_pairs.into_iter().rev().enumerate() {
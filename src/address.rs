@@ -0,0 +1,210 @@
+//! Where the driver station looks for the roboRIO.
+//!
+//! A [`ConnectionTarget`] holds any number of [`RobotAddress`] candidates.
+//! Control packets are sent to every candidate at once until one of them answers,
+//! after which the driver station locks onto that address until the robot is lost.
+
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    time::Duration,
+};
+
+use tokio::{net::lookup_host, time::timeout};
+
+/// The address of a roboRIO plugged in over USB.
+pub const USB_IP: Ipv4Addr = Ipv4Addr::new(172, 22, 11, 2);
+/// The address of a robot program simulated on this machine.
+pub const SIM_IP: Ipv4Addr = Ipv4Addr::LOCALHOST;
+
+// Hostnames that aren't resolved in time are retried on the next connection attempt
+const RESOLVE_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RobotAddress {
+    /// The static `10.TE.AM.2` address on the team's network.
+    Team(u16),
+    /// The `roboRIO-TEAM-FRC.local` mDNS hostname.
+    ///
+    /// Hostnames are looked up through the system resolver,
+    /// which must support mDNS for this to resolve.
+    Mdns(u16),
+    /// The roboRIO over USB, at [`USB_IP`].
+    Usb,
+    /// A simulated robot program, at [`SIM_IP`].
+    Simulation,
+    Ip(IpAddr),
+    Hostname(String),
+}
+
+impl RobotAddress {
+    /// The hostname to resolve, if this address is not a fixed IP.
+    pub fn hostname(&self) -> Option<String> {
+        match self {
+            RobotAddress::Mdns(team) => Some(format!("roboRIO-{team}-FRC.local")),
+            RobotAddress::Hostname(hostname) => Some(hostname.clone()),
+            _ => None,
+        }
+    }
+
+    /// Looks up the IPs the robot may be at.
+    ///
+    /// Hostnames that can't be resolved give no IPs.
+    pub async fn resolve(&self) -> Vec<IpAddr> {
+        match self {
+            RobotAddress::Team(team) => vec![ip_from_team(*team).into()],
+            RobotAddress::Usb => vec![USB_IP.into()],
+            RobotAddress::Simulation => vec![SIM_IP.into()],
+            RobotAddress::Ip(ip) => vec![*ip],
+            RobotAddress::Mdns(_) | RobotAddress::Hostname(_) => {
+                let Some(hostname) = self.hostname() else {
+                    return Vec::new();
+                };
+
+                match timeout(RESOLVE_TIMEOUT, lookup_host((hostname, 0))).await {
+                    Ok(Ok(addrs)) => addrs.map(|addr| addr.ip()).collect(),
+                    _ => Vec::new(),
+                }
+            }
+        }
+    }
+
+    fn with_team(self, team: u16) -> RobotAddress {
+        match self {
+            RobotAddress::Team(_) => RobotAddress::Team(team),
+            RobotAddress::Mdns(_) => RobotAddress::Mdns(team),
+            address => address,
+        }
+    }
+}
+
+impl From<IpAddr> for RobotAddress {
+    fn from(ip: IpAddr) -> Self {
+        RobotAddress::Ip(ip)
+    }
+}
+
+impl From<Ipv4Addr> for RobotAddress {
+    fn from(ip: Ipv4Addr) -> Self {
+        RobotAddress::Ip(ip.into())
+    }
+}
+
+/// The candidate addresses to try when connecting to the robot.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConnectionTarget {
+    candidates: Vec<RobotAddress>,
+}
+
+impl ConnectionTarget {
+    /// Creates a target without any candidates.
+    pub fn new() -> Self {
+        ConnectionTarget::default()
+    }
+
+    /// Tries every way the team's real robot is usually reached:
+    /// the static team IP, the mDNS hostname, and USB.
+    ///
+    /// A simulated robot is only tried when [`RobotAddress::Simulation`] is added.
+    pub fn team(team: u16) -> Self {
+        ConnectionTarget::new()
+            .with_address(RobotAddress::Team(team))
+            .with_address(RobotAddress::Mdns(team))
+            .with_address(RobotAddress::Usb)
+    }
+
+    /// Adds a candidate, unless it is already being tried.
+    pub fn with_address(mut self, address: impl Into<RobotAddress>) -> Self {
+        let address = address.into();
+        if !self.candidates.contains(&address) {
+            self.candidates.push(address);
+        }
+
+        self
+    }
+
+    pub fn candidates(&self) -> &[RobotAddress] {
+        &self.candidates
+    }
+
+    /// Points the team's IP and hostname candidates at another team.
+    pub fn with_team(self, team: u16) -> Self {
+        self.candidates
+            .into_iter()
+            .map(|address| address.with_team(team))
+            .fold(ConnectionTarget::new(), ConnectionTarget::with_address)
+    }
+
+    /// Resolves every candidate at once, giving their IPs in the order the candidates were added.
+    ///
+    /// IPv6 addresses are left out, since the driver station's sockets are bound to IPv4.
+    pub(crate) async fn resolve(&self) -> Vec<IpAddr> {
+        let lookups: Vec<_> = self
+            .candidates
+            .iter()
+            .cloned()
+            .map(|address| tokio::spawn(async move { address.resolve().await }))
+            .collect();
+
+        let mut ips = Vec::new();
+        for lookup in lookups {
+            for ip in lookup.await.unwrap_or_default() {
+                if ip.is_ipv4() && !ips.contains(&ip) {
+                    ips.push(ip);
+                }
+            }
+        }
+
+        ips
+    }
+}
+
+/// Constructs the RoboRIO IP address from the given team number.
+pub fn ip_from_team(team: u16) -> Ipv4Addr {
+    Ipv4Addr::new(10, (team / 100) as u8, (team % 100) as u8, 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv6Addr;
+
+    use super::*;
+
+    #[test]
+    fn retarget_team_candidates() {
+        let target = ConnectionTarget::team(8891)
+            .with_address(RobotAddress::Simulation)
+            .with_address(Ipv4Addr::new(10, 0, 0, 2))
+            .with_team(254);
+
+        assert_eq!(
+            target.candidates(),
+            [
+                RobotAddress::Team(254),
+                RobotAddress::Mdns(254),
+                RobotAddress::Usb,
+                RobotAddress::Simulation,
+                RobotAddress::Ip(Ipv4Addr::new(10, 0, 0, 2).into()),
+            ]
+        );
+        assert_eq!(
+            target.candidates()[1].hostname().as_deref(),
+            Some("roboRIO-254-FRC.local")
+        );
+    }
+
+    #[tokio::test]
+    async fn resolve_fixed_candidates() {
+        let ips = ConnectionTarget::new()
+            .with_address(RobotAddress::Team(8891))
+            .with_address(RobotAddress::Usb)
+            .with_address(Ipv4Addr::new(172, 22, 11, 2))
+            .with_address(IpAddr::from(Ipv6Addr::LOCALHOST))
+            .with_address(RobotAddress::Hostname("localhost".to_string()))
+            .resolve()
+            .await;
+
+        assert_eq!(ips[0], IpAddr::from([10, 88, 91, 2]));
+        assert_eq!(ips[1], IpAddr::from(USB_IP));
+        assert!(ips[2..].iter().all(|ip| ip.is_loopback() && ip.is_ipv4()));
+    }
+}
@@ -0,0 +1,210 @@
+use std::{net::IpAddr, time::Duration};
+
+use crate::{timer::MatchTimer, ConnectionTarget, Error, Robot};
+
+const UDP_PORT: u16 = 1110;
+const TCP_PORT: u16 = 1740;
+const DS_UDP_TX_PORT: u16 = 56789;
+const DS_UDP_RX_PORT: u16 = 1150;
+const FMS_TCP_PORT: u16 = 1750;
+const FMS_UDP_PORT: u16 = 1160;
+const DS_FMS_RX_PORT: u16 = 1121;
+
+const UDP_PERIOD: Duration = Duration::from_millis(20);
+const TCP_PERIOD: Duration = Duration::from_secs(1);
+// Shorter periods are raised to this, since a zero period can't be ticked
+const MIN_PERIOD: Duration = Duration::from_millis(1);
+const TIMEOUT: Duration = Duration::from_millis(500);
+const LOW_BATTERY: f32 = 10.0;
+
+/// Configures a [`Robot`] before connecting to it, created with [`Robot::builder`].
+///
+/// Every setting defaults to what the official driver station uses.
+/// Changing the driver station's ports allows several robots to be driven from one machine,
+/// as long as each robot replies to its own receive port.
+#[derive(Debug, Clone)]
+pub struct RobotBuilder {
+    team_number: u16,
+    target: Option<ConnectionTarget>,
+    expected_ssid: Option<String>,
+    config: Config,
+}
+
+/// The ports and timings shared by the background tasks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Config {
+    pub(crate) udp_port: u16,
+    pub(crate) tcp_port: u16,
+    pub(crate) ds_udp_tx_port: u16,
+    pub(crate) ds_udp_rx_port: u16,
+    pub(crate) udp_period: Duration,
+    pub(crate) tcp_period: Duration,
+    pub(crate) timeout: Duration,
+    pub(crate) low_battery: f32,
+    pub(crate) match_timer: Option<MatchTimer>,
+    pub(crate) fms: Option<IpAddr>,
+    pub(crate) fms_tcp_port: u16,
+    pub(crate) fms_udp_port: u16,
+    pub(crate) ds_fms_rx_port: u16,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            udp_port: UDP_PORT,
+            tcp_port: TCP_PORT,
+            ds_udp_tx_port: DS_UDP_TX_PORT,
+            ds_udp_rx_port: DS_UDP_RX_PORT,
+            udp_period: UDP_PERIOD,
+            tcp_period: TCP_PERIOD,
+            timeout: TIMEOUT,
+            low_battery: LOW_BATTERY,
+            match_timer: None,
+            fms: None,
+            fms_tcp_port: FMS_TCP_PORT,
+            fms_udp_port: FMS_UDP_PORT,
+            ds_fms_rx_port: DS_FMS_RX_PORT,
+        }
+    }
+}
+
+impl RobotBuilder {
+    pub fn new(team_number: u16) -> Self {
+        RobotBuilder {
+            team_number,
+            target: None,
+            expected_ssid: None,
+            config: Config::default(),
+        }
+    }
+
+    /// Sets the addresses to look for the robot at,
+    /// instead of the defaults from [`ConnectionTarget::team`].
+    pub fn with_target(mut self, target: ConnectionTarget) -> Self {
+        self.target = Some(target);
+        self
+    }
+
+    /// Sets the port the robot receives control packets on.
+    pub fn with_udp_port(mut self, port: u16) -> Self {
+        self.config.udp_port = port;
+        self
+    }
+
+    /// Sets the port the robot accepts the TCP connection on.
+    pub fn with_tcp_port(mut self, port: u16) -> Self {
+        self.config.tcp_port = port;
+        self
+    }
+
+    /// Sets the port control packets are sent from, with `0` picking any free port.
+    pub fn with_ds_udp_tx_port(mut self, port: u16) -> Self {
+        self.config.ds_udp_tx_port = port;
+        self
+    }
+
+    /// Sets the port the robot's replies are received on.
+    pub fn with_ds_udp_rx_port(mut self, port: u16) -> Self {
+        self.config.ds_udp_rx_port = port;
+        self
+    }
+
+    /// Sets how often a control packet is sent, at most once a millisecond.
+    pub fn with_udp_period(mut self, period: Duration) -> Self {
+        self.config.udp_period = period.max(MIN_PERIOD);
+        self
+    }
+
+    /// Sets how often a TCP packet is sent, at most once a millisecond.
+    pub fn with_tcp_period(mut self, period: Duration) -> Self {
+        self.config.tcp_period = period.max(MIN_PERIOD);
+        self
+    }
+
+    /// Sets how long the robot can go without replying before it is considered disconnected.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeout = timeout;
+        self
+    }
+
+    /// Sets the voltage the battery has to drop below to send [`Event::BatteryLow`](crate::event::Event::BatteryLow).
+    pub fn with_low_battery(mut self, voltage: f32) -> Self {
+        self.config.low_battery = voltage;
+        self
+    }
+
+    /// Counts down each period of the match from the start, see [`Robot::set_match_timer`].
+    pub fn with_match_timer(mut self, timer: MatchTimer) -> Self {
+        self.config.match_timer = Some(timer);
+        self
+    }
+
+    /// Lets the FMS at `ip` control the robot, such as [`FIELD_IP`](crate::fms::FIELD_IP) at an event.
+    ///
+    /// The FMS then decides the robot's station, mode, and whether it's enabled,
+    /// and is sent back whether the robot is connected and its battery voltage.
+    pub fn with_fms(mut self, ip: IpAddr) -> Self {
+        self.config.fms = Some(ip);
+        self
+    }
+
+    /// Sets the port the FMS accepts the TCP connection on.
+    pub fn with_fms_tcp_port(mut self, port: u16) -> Self {
+        self.config.fms_tcp_port = port;
+        self
+    }
+
+    /// Sets the port the FMS receives status packets on.
+    pub fn with_fms_udp_port(mut self, port: u16) -> Self {
+        self.config.fms_udp_port = port;
+        self
+    }
+
+    /// Sets the port the FMS's control packets are received on.
+    pub fn with_ds_fms_rx_port(mut self, port: u16) -> Self {
+        self.config.ds_fms_rx_port = port;
+        self
+    }
+
+    /// Sets the network the robot's radio should be on, checked by [`Robot::radio_status`].
+    pub fn with_expected_ssid(mut self, ssid: impl Into<String>) -> Self {
+        self.expected_ssid = Some(ssid.into());
+        self
+    }
+
+    /// Connects to the robot.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the driver station's ports can't be bound, see [`RobotBuilder::try_build`].
+    pub fn build(self) -> Robot {
+        self.try_build()
+            .expect("unable to start the driver station")
+    }
+
+    /// Connects to the robot, or fails if the driver station's ports can't be bound.
+    pub fn try_build(self) -> Result<Robot, Error> {
+        let target = self
+            .target
+            .unwrap_or_else(|| ConnectionTarget::team(self.team_number));
+
+        let mut robot = Robot::spawn(self.team_number, target, self.config)?;
+        robot.expected_ssid = self.expected_ssid;
+        Ok(robot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raise_zero_periods() {
+        let builder = RobotBuilder::new(8891)
+            .with_udp_period(Duration::ZERO)
+            .with_tcp_period(Duration::ZERO);
+
+        assert_eq!(builder.config.udp_period, MIN_PERIOD);
+        assert_eq!(builder.config.tcp_period, MIN_PERIOD);
+    }
+}
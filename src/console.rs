@@ -0,0 +1,196 @@
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::recv::tcp::{Flags, Tag};
+
+// The number of messages a slow `Console` can fall behind before it starts missing them
+pub(crate) const CONSOLE_CAPACITY: usize = 1024;
+
+/// A message printed by the robot program.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    /// A line written to the roboRIO's standard output.
+    Output {
+        /// Seconds since the robot program started.
+        timestamp: f32,
+        sequence: u16,
+        message: String,
+    },
+    /// An error or warning reported through the HAL.
+    Error {
+        /// Seconds since the robot program started.
+        timestamp: f32,
+        sequence: u16,
+        error_code: i32,
+        flags: Flags,
+        details: String,
+        location: String,
+        call_stack: String,
+    },
+}
+
+impl Message {
+    /// Converts a TCP tag into a console message,
+    /// returning `None` if the tag is not console output.
+    pub fn from_tag(tag: Tag) -> Option<Message> {
+        match tag {
+            Tag::StandardOutput {
+                timestamp,
+                sequence,
+                message,
+            } => Some(Message::Output {
+                timestamp,
+                sequence,
+                message: message.to_string_lossy().into_owned(),
+            }),
+            Tag::ErrorMessage {
+                timestamp,
+                sequence,
+                error_code,
+                flags,
+                details,
+                location,
+                call_stack,
+            } => Some(Message::Error {
+                timestamp,
+                sequence,
+                error_code,
+                flags,
+                details: details.to_string_lossy().into_owned(),
+                location: location.to_string_lossy().into_owned(),
+                call_stack: call_stack.to_string_lossy().into_owned(),
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn timestamp(&self) -> f32 {
+        match self {
+            Message::Output { timestamp, .. } => *timestamp,
+            Message::Error { timestamp, .. } => *timestamp,
+        }
+    }
+
+    pub fn sequence(&self) -> u16 {
+        match self {
+            Message::Output { sequence, .. } => *sequence,
+            Message::Error { sequence, .. } => *sequence,
+        }
+    }
+}
+
+/// A handle to the robot's console output, created with [`Robot::console`](crate::Robot::console).
+///
+/// Each `Console` receives every message sent after it was created.
+/// If it falls too far behind the oldest messages are skipped.
+#[derive(Debug)]
+pub struct Console {
+    rx: broadcast::Receiver<Message>,
+}
+
+impl Console {
+    pub(crate) fn new(rx: broadcast::Receiver<Message>) -> Console {
+        Console { rx }
+    }
+
+    /// Waits for the next message from the robot.
+    ///
+    /// Returns `None` once the [`Robot`](crate::Robot)'s background tasks have all stopped,
+    /// which dropping the `Robot` alone does not do.
+    pub async fn recv(&mut self) -> Option<Message> {
+        loop {
+            match self.rx.recv().await {
+                Ok(message) => return Some(message),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+#[cfg(feature = "sync")]
+impl Iterator for Console {
+    type Item = Message;
+
+    /// Blocks until the next message from the robot.
+    ///
+    /// Returns `None` once the [`Robot`](crate::Robot)'s background tasks have all stopped,
+    /// which dropping the `Robot` alone does not do.
+    fn next(&mut self) -> Option<Message> {
+        loop {
+            match self.rx.blocking_recv() {
+                Ok(message) => return Some(message),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(sequence: u16) -> Message {
+        Message::Output {
+            timestamp: 0.0,
+            sequence,
+            message: String::new(),
+        }
+    }
+
+    #[test]
+    fn messages_from_tags() {
+        let output = Tag::StandardOutput {
+            timestamp: 1.5,
+            sequence: 3,
+            message: c"Hello".into(),
+        };
+        assert_eq!(
+            Message::from_tag(output),
+            Some(Message::Output {
+                timestamp: 1.5,
+                sequence: 3,
+                message: "Hello".to_owned(),
+            })
+        );
+
+        let error = Tag::ErrorMessage {
+            timestamp: 2.25,
+            sequence: 4,
+            error_code: -44,
+            flags: Flags::from_bits(0x01),
+            details: c"Unhandled exception".into(),
+            location: c"Robot.java".into(),
+            call_stack: c"\tat main".into(),
+        };
+        assert_eq!(
+            Message::from_tag(error),
+            Some(Message::Error {
+                timestamp: 2.25,
+                sequence: 4,
+                error_code: -44,
+                flags: Flags::from_bits(0x01),
+                details: "Unhandled exception".to_owned(),
+                location: "Robot.java".to_owned(),
+                call_stack: "\tat main".to_owned(),
+            })
+        );
+
+        assert_eq!(Message::from_tag(Tag::Radio("linked".to_owned())), None);
+    }
+
+    #[tokio::test]
+    async fn skip_missed_messages() {
+        let (tx, rx) = broadcast::channel(2);
+        let mut console = Console::new(rx);
+        for sequence in 0..3 {
+            tx.send(output(sequence)).unwrap();
+        }
+
+        assert_eq!(console.recv().await, Some(output(1)));
+        assert_eq!(console.recv().await, Some(output(2)));
+
+        drop(tx);
+        assert_eq!(console.recv().await, None);
+    }
+}
@@ -0,0 +1,129 @@
+use std::time::Instant;
+
+use crate::recv::udp::{CpuUsage, Tag};
+
+/// The latest health readings the roboRIO has sent, from [`Robot::diagnostics`](crate::Robot::diagnostics).
+///
+/// The roboRIO sends each reading on its own schedule,
+/// so readings are `None` until the first one arrives and are cleared when the robot disconnects.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Diagnostics {
+    pub cpu: Option<Reading<[CpuUsage; 2]>>,
+    /// Bytes of RAM available.
+    pub free_ram: Option<Reading<u32>>,
+    /// Bytes of disk space available.
+    pub free_disk: Option<Reading<u32>>,
+    pub can: Option<Reading<CanMetrics>>,
+    pub pdp: Option<Reading<Pdp>>,
+}
+
+/// A diagnostic value and when it was received.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reading<T> {
+    pub value: T,
+    pub received: Instant,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CanMetrics {
+    /// The percentage of the bus in use.
+    pub utilization: f32,
+    pub bus_off: u32,
+    pub tx_full: u32,
+    pub rx_errors: u8,
+    pub tx_errors: u8,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Pdp {
+    /// The current through each channel in amps.
+    pub currents: [f32; 16],
+    /// The total current in amps.
+    pub total_current: f32,
+    /// The temperature in degrees Celsius.
+    pub temperature: f32,
+}
+
+impl Diagnostics {
+    /// Keeps the reading from the tag, if it is a diagnostic one.
+    pub(crate) fn apply(&mut self, tag: &Tag, received: Instant) {
+        match *tag {
+            Tag::CPUInfo { usage, .. } => self.cpu = Some(Reading::new(usage, received)),
+            Tag::RAMInfo { free_space, .. } => {
+                self.free_ram = Some(Reading::new(free_space, received))
+            }
+            Tag::DiskInfo { free_space } => {
+                self.free_disk = Some(Reading::new(free_space, received))
+            }
+            Tag::CANMetrics {
+                utilization,
+                bus_off,
+                tx_full,
+                rx_errors,
+                tx_errors,
+            } => {
+                let metrics = CanMetrics {
+                    utilization,
+                    bus_off,
+                    tx_full,
+                    rx_errors,
+                    tx_errors,
+                };
+                self.can = Some(Reading::new(metrics, received));
+            }
+            Tag::PDPLog {
+                currents,
+                total_current,
+                temperature,
+            } => {
+                let pdp = Pdp {
+                    currents,
+                    total_current,
+                    temperature,
+                };
+                self.pdp = Some(Reading::new(pdp, received));
+            }
+            Tag::JoystickOutput { .. } => {}
+        }
+    }
+}
+
+impl<T> Reading<T> {
+    fn new(value: T, received: Instant) -> Reading<T> {
+        Reading { value, received }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keep_latest_readings() {
+        let mut diagnostics = Diagnostics::default();
+        let first = Instant::now();
+        let usage = CpuUsage {
+            critical: 9.5,
+            above_normal: 0.0,
+            normal: 0.0,
+            low: 1.5,
+        };
+
+        diagnostics.apply(
+            &Tag::CPUInfo {
+                num_cpus: 2,
+                usage: [usage; 2],
+            },
+            first,
+        );
+        diagnostics.apply(&Tag::DiskInfo { free_space: 1024 }, first);
+
+        let second = Instant::now();
+        diagnostics.apply(&Tag::DiskInfo { free_space: 512 }, second);
+
+        assert_eq!(diagnostics.cpu.unwrap().value[1].total(), 11.0);
+        assert_eq!(diagnostics.cpu.unwrap().received, first);
+        assert_eq!(diagnostics.free_disk, Some(Reading::new(512, second)));
+        assert_eq!(diagnostics.free_ram, None);
+    }
+}
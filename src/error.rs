@@ -0,0 +1,76 @@
+use std::{
+    fmt, io,
+    sync::{Arc, OnceLock},
+};
+
+use crate::recv::{tcp::TcpParseError, udp::UdpParseError};
+
+#[derive(Debug, Clone)]
+pub enum Error {
+    /// A socket couldn't be bound, or failed while in use.
+    Io(Arc<io::Error>),
+    /// A UDP packet from the robot couldn't be parsed.
+    Udp(UdpParseError),
+    /// A TCP frame from the robot couldn't be parsed.
+    Tcp(TcpParseError),
+    /// A background task has stopped, so the robot can no longer be controlled.
+    Stopped,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "{err}"),
+            Error::Udp(err) => write!(f, "unable to parse UDP packet: {err:?}"),
+            Error::Tcp(err) => write!(f, "unable to parse TCP frame: {err:?}"),
+            Error::Stopped => write!(f, "the connection to the robot has stopped"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(Arc::new(err))
+    }
+}
+
+impl From<UdpParseError> for Error {
+    fn from(err: UdpParseError) -> Self {
+        Error::Udp(err)
+    }
+}
+
+impl From<TcpParseError> for Error {
+    fn from(err: TcpParseError) -> Self {
+        Error::Tcp(err)
+    }
+}
+
+/// The error a background task stopped with, shared with the handles that send to it.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Failure(Arc<OnceLock<Error>>);
+
+impl Failure {
+    /// Keeps the first error, since later ones are usually caused by it.
+    pub(crate) fn set(&self, err: Error) {
+        let _ = self.0.set(err);
+    }
+
+    pub(crate) fn get(&self) -> Option<Error> {
+        self.0.get().cloned()
+    }
+
+    /// The error to give when a task can no longer be sent to.
+    pub(crate) fn stopped(&self) -> Error {
+        self.get().unwrap_or(Error::Stopped)
+    }
+}
@@ -0,0 +1,183 @@
+use crate::{
+    recv::udp::{CodeStatus, UdpResponse},
+    Mode,
+};
+
+// The number of events a slow subscriber can fall behind before it starts missing them
+pub(crate) const EVENTS_CAPACITY: usize = 256;
+
+// How far the battery has to recover before another `BatteryLow` is sent
+const BATTERY_HYSTERESIS: f32 = 0.5;
+
+/// A change in the robot's state, received from [`Robot::subscribe`](crate::Robot::subscribe).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    /// The robot answered after being disconnected.
+    Connected,
+    /// The robot stopped answering for longer than the timeout.
+    Disconnected,
+    Enabled,
+    Disabled,
+    ModeChanged(Mode),
+    EstopTriggered,
+    /// The robot program started running, such as after a deploy or code restart.
+    CodeStarted,
+    /// The roboRIO disabled its outputs because the battery voltage dropped too low.
+    Brownout,
+    /// The battery voltage dropped below the low battery threshold.
+    BatteryLow(f32),
+}
+
+/// Derives events from successive responses, owned by the UDP thread.
+#[derive(Debug)]
+pub(crate) struct Tracker {
+    low_battery: f32,
+    battery_low: bool,
+    last: Option<Snapshot>,
+}
+
+/// The parts of a response that events are derived from.
+///
+/// The default matches the state of a robot that hasn't connected yet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Snapshot {
+    enabled: bool,
+    estopped: bool,
+    mode: Mode,
+    code: CodeStatus,
+    brownout: bool,
+}
+
+impl Default for Snapshot {
+    fn default() -> Self {
+        Snapshot {
+            enabled: false,
+            estopped: false,
+            mode: Mode::Teleoperated,
+            code: CodeStatus::Initializing,
+            brownout: false,
+        }
+    }
+}
+
+impl Snapshot {
+    fn new(response: &UdpResponse) -> Snapshot {
+        Snapshot {
+            enabled: response.status.enabled(),
+            estopped: response.status.estopped(),
+            mode: response.status.mode(),
+            code: response.trace.robot_code(),
+            brownout: response.status.brownout(),
+        }
+    }
+}
+
+impl Tracker {
+    pub(crate) fn new(low_battery: f32) -> Tracker {
+        Tracker {
+            low_battery,
+            battery_low: false,
+            last: None,
+        }
+    }
+
+    /// The events caused by the robot's latest response.
+    pub(crate) fn update(&mut self, response: &UdpResponse) -> Vec<Event> {
+        let mut events = Vec::new();
+        let current = Snapshot::new(response);
+        let last = match self.last {
+            Some(last) => last,
+            None => {
+                events.push(Event::Connected);
+                Snapshot::default()
+            }
+        };
+
+        if current.enabled != last.enabled {
+            events.push(match current.enabled {
+                true => Event::Enabled,
+                false => Event::Disabled,
+            });
+        }
+        if current.mode != last.mode {
+            events.push(Event::ModeChanged(current.mode));
+        }
+        if current.estopped && !last.estopped {
+            events.push(Event::EstopTriggered);
+        }
+        if current.code == CodeStatus::Running && last.code != CodeStatus::Running {
+            events.push(Event::CodeStarted);
+        }
+        if current.brownout && !last.brownout {
+            events.push(Event::Brownout);
+        }
+
+        // Robots without a battery reading report zero volts
+        let voltage = response.battery.voltage();
+        if voltage > 0.0 && voltage < self.low_battery && !self.battery_low {
+            self.battery_low = true;
+            events.push(Event::BatteryLow(voltage));
+        } else if voltage >= self.low_battery + BATTERY_HYSTERESIS {
+            self.battery_low = false;
+        }
+
+        self.last = Some(current);
+        events
+    }
+
+    /// Forgets the robot's state once it stops answering.
+    pub(crate) fn disconnect(&mut self) -> Option<Event> {
+        self.battery_low = false;
+        self.last.take().map(|_| Event::Disconnected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recv::udp::{Battery, Status, Trace};
+
+    fn response(status: u8, trace: u8, voltage: f32) -> UdpResponse {
+        UdpResponse {
+            sequence: 0,
+            comm_version: 0x01,
+            status: Status::from_bits(status),
+            trace: Trace::from_bits(trace),
+            battery: Battery::from_voltage(voltage),
+            first_conn: false,
+            tags: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn track_state_changes() {
+        let mut tracker = Tracker::new(10.0);
+        let running = Trace::ROBOT_CODE_MASK | Trace::IS_RIO_MASK;
+
+        assert_eq!(
+            tracker.update(&response(0x00, running, 12.5)),
+            [Event::Connected, Event::CodeStarted]
+        );
+        assert_eq!(tracker.update(&response(0x00, running, 12.5)), []);
+        assert_eq!(
+            tracker.update(&response(Status::ENABLED_MASK | 0x02, running, 12.5)),
+            [Event::Enabled, Event::ModeChanged(Mode::Autonomous)]
+        );
+        assert_eq!(
+            tracker.update(&response(Status::ESTOP_MASK, running, 9.5)),
+            [
+                Event::Disabled,
+                Event::ModeChanged(Mode::Teleoperated),
+                Event::EstopTriggered,
+                Event::BatteryLow(9.5),
+            ]
+        );
+        assert_eq!(
+            tracker.update(&response(Status::ESTOP_MASK, running, 9.0)),
+            []
+        );
+
+        assert_eq!(tracker.disconnect(), Some(Event::Disconnected));
+        assert_eq!(tracker.disconnect(), None);
+    }
+}
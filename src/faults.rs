@@ -0,0 +1,346 @@
+//! A history of brownouts and faults, like the diagnostics tab of the official driver station.
+
+use std::{collections::VecDeque, mem, time::SystemTime};
+
+use crate::recv::tcp::Tag;
+
+// Older sessions are forgotten once there are this many
+const MAX_SESSIONS: usize = 64;
+
+/// How many of each fault the roboRIO has counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct FaultCounts {
+    pub comms: u16,
+    pub twelve_volt: u16,
+    pub six_volt: u16,
+    pub five_volt: u16,
+    pub three_three_volt: u16,
+}
+
+impl FaultCounts {
+    /// How many faults of each kind were counted since `earlier`.
+    pub fn since(&self, earlier: &FaultCounts) -> FaultCounts {
+        FaultCounts {
+            comms: self.comms.saturating_sub(earlier.comms),
+            twelve_volt: self.twelve_volt.saturating_sub(earlier.twelve_volt),
+            six_volt: self.six_volt.saturating_sub(earlier.six_volt),
+            five_volt: self.five_volt.saturating_sub(earlier.five_volt),
+            three_three_volt: self
+                .three_three_volt
+                .saturating_sub(earlier.three_three_volt),
+        }
+    }
+
+    pub fn total(&self) -> u32 {
+        self.comms as u32
+            + self.twelve_volt as u32
+            + self.six_volt as u32
+            + self.five_volt as u32
+            + self.three_three_volt as u32
+    }
+
+    fn add(&self, other: &FaultCounts) -> FaultCounts {
+        FaultCounts {
+            comms: self.comms.saturating_add(other.comms),
+            twelve_volt: self.twelve_volt.saturating_add(other.twelve_volt),
+            six_volt: self.six_volt.saturating_add(other.six_volt),
+            five_volt: self.five_volt.saturating_add(other.five_volt),
+            three_three_volt: self.three_three_volt.saturating_add(other.three_three_volt),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Brownout {
+    pub start: SystemTime,
+    /// When the brownout ended, or `None` if it is still going.
+    pub end: Option<SystemTime>,
+}
+
+/// Faults that were counted between two reports from the roboRIO.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FaultRecord {
+    /// When the report with the new faults was received.
+    pub time: SystemTime,
+    pub faults: FaultCounts,
+}
+
+/// Everything that happened while connected to the robot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    connected: SystemTime,
+    disconnected: Option<SystemTime>,
+    brownouts: Vec<Brownout>,
+    faults: Vec<FaultRecord>,
+    // The roboRIO counts faults since it booted,
+    // so the first report of each tag is the baseline for the session
+    first_counts: FaultCounts,
+    counts: FaultCounts,
+    disable_reported: bool,
+    rail_reported: bool,
+}
+
+impl Session {
+    fn new(connected: SystemTime) -> Session {
+        Session {
+            connected,
+            disconnected: None,
+            brownouts: Vec::new(),
+            faults: Vec::new(),
+            first_counts: FaultCounts::default(),
+            counts: FaultCounts::default(),
+            disable_reported: false,
+            rail_reported: false,
+        }
+    }
+
+    pub fn connected(&self) -> SystemTime {
+        self.connected
+    }
+
+    /// When the robot was lost, or `None` if this is the current session.
+    pub fn disconnected(&self) -> Option<SystemTime> {
+        self.disconnected
+    }
+
+    pub fn brownouts(&self) -> &[Brownout] {
+        &self.brownouts
+    }
+
+    /// Every report that counted new faults.
+    pub fn faults(&self) -> &[FaultRecord] {
+        &self.faults
+    }
+
+    /// How many faults of each kind were counted during the session.
+    pub fn fault_deltas(&self) -> FaultCounts {
+        self.counts.since(&self.first_counts)
+    }
+
+    fn update_counts(
+        &mut self,
+        baseline: bool,
+        update: impl Fn(&mut FaultCounts),
+        time: SystemTime,
+    ) {
+        let previous = self.counts;
+        update(&mut self.counts);
+
+        if baseline {
+            update(&mut self.first_counts);
+            return;
+        }
+
+        let faults = self.counts.since(&previous);
+        if faults.total() > 0 {
+            self.faults.push(FaultRecord { time, faults });
+        }
+    }
+
+    fn end_brownout(&mut self, time: SystemTime) {
+        if let Some(brownout) = self.brownouts.last_mut() {
+            if brownout.end.is_none() {
+                brownout.end = Some(time);
+            }
+        }
+    }
+}
+
+/// The brownouts and faults seen in each session with the robot, oldest first.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FaultLog {
+    sessions: VecDeque<Session>,
+}
+
+impl FaultLog {
+    pub fn sessions(&self) -> impl Iterator<Item = &Session> {
+        self.sessions.iter()
+    }
+
+    /// The session with the robot that is currently connected.
+    pub fn current(&self) -> Option<&Session> {
+        self.sessions
+            .back()
+            .filter(|session| session.disconnected.is_none())
+    }
+
+    pub fn brownouts(&self) -> impl Iterator<Item = &Brownout> {
+        self.sessions
+            .iter()
+            .flat_map(|session| session.brownouts.iter())
+    }
+
+    pub fn faults(&self) -> impl Iterator<Item = &FaultRecord> {
+        self.sessions
+            .iter()
+            .flat_map(|session| session.faults.iter())
+    }
+
+    /// How many faults of each kind were counted across every session.
+    pub fn fault_deltas(&self) -> FaultCounts {
+        self.sessions
+            .iter()
+            .fold(FaultCounts::default(), |total, session| {
+                total.add(&session.fault_deltas())
+            })
+    }
+
+    pub(crate) fn connect(&mut self, time: SystemTime) {
+        if self.sessions.len() == MAX_SESSIONS {
+            self.sessions.pop_front();
+        }
+
+        self.sessions.push_back(Session::new(time));
+    }
+
+    pub(crate) fn disconnect(&mut self, time: SystemTime) {
+        if let Some(session) = self.current_mut() {
+            session.end_brownout(time);
+            session.disconnected = Some(time);
+        }
+    }
+
+    /// Starts or ends a brownout as the robot's status changes.
+    pub(crate) fn set_brownout(&mut self, brownout: bool, time: SystemTime) {
+        let Some(session) = self.current_mut() else {
+            return;
+        };
+
+        let browning_out = session
+            .brownouts
+            .last()
+            .is_some_and(|brownout| brownout.end.is_none());
+
+        if brownout && !browning_out {
+            session.brownouts.push(Brownout {
+                start: time,
+                end: None,
+            });
+        } else if !brownout {
+            session.end_brownout(time);
+        }
+    }
+
+    /// Records the fault counters from the tag, if it has any.
+    pub(crate) fn apply(&mut self, tag: &Tag, time: SystemTime) {
+        let Some(session) = self.current_mut() else {
+            return;
+        };
+
+        match *tag {
+            Tag::DisableFaults { comms, twelve_volt } => {
+                let baseline = !mem::replace(&mut session.disable_reported, true);
+                session.update_counts(
+                    baseline,
+                    |counts| {
+                        counts.comms = comms;
+                        counts.twelve_volt = twelve_volt;
+                    },
+                    time,
+                );
+            }
+            Tag::RailFaults {
+                six_volt,
+                five_volt,
+                three_three_volt,
+            } => {
+                let baseline = !mem::replace(&mut session.rail_reported, true);
+                session.update_counts(
+                    baseline,
+                    |counts| {
+                        counts.six_volt = six_volt;
+                        counts.five_volt = five_volt;
+                        counts.three_three_volt = three_three_volt;
+                    },
+                    time,
+                );
+            }
+            _ => {}
+        }
+    }
+
+    fn current_mut(&mut self) -> Option<&mut Session> {
+        self.sessions
+            .back_mut()
+            .filter(|session| session.disconnected.is_none())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn count_faults_per_session() {
+        let mut log = FaultLog::default();
+        let disable = |comms, twelve_volt| Tag::DisableFaults { comms, twelve_volt };
+        let rail = |six_volt| Tag::RailFaults {
+            six_volt,
+            five_volt: 0,
+            three_three_volt: 0,
+        };
+
+        log.connect(at(0));
+        log.apply(&disable(3, 1), at(1));
+        log.apply(&rail(7), at(1));
+        log.apply(&disable(5, 1), at(2));
+        log.apply(&rail(8), at(3));
+        log.disconnect(at(4));
+
+        log.connect(at(10));
+        log.apply(&disable(5, 1), at(11));
+        log.apply(&disable(5, 2), at(12));
+
+        let sessions: Vec<_> = log.sessions().collect();
+        assert_eq!(sessions[0].disconnected(), Some(at(4)));
+        assert_eq!(
+            sessions[0].fault_deltas(),
+            FaultCounts {
+                comms: 2,
+                six_volt: 1,
+                ..Default::default()
+            }
+        );
+        assert_eq!(sessions[0].faults().len(), 2);
+        assert_eq!(sessions[0].faults()[1].time, at(3));
+
+        assert_eq!(log.current().unwrap().fault_deltas().twelve_volt, 1);
+        assert_eq!(log.fault_deltas().total(), 4);
+    }
+
+    #[test]
+    fn record_brownouts() {
+        let mut log = FaultLog::default();
+
+        // Ignored without a session to record it in
+        log.set_brownout(true, at(0));
+
+        log.connect(at(1));
+        log.set_brownout(true, at(2));
+        log.set_brownout(true, at(3));
+        log.set_brownout(false, at(4));
+        log.set_brownout(true, at(5));
+        log.disconnect(at(6));
+
+        let brownouts: Vec<_> = log.brownouts().copied().collect();
+        assert_eq!(
+            brownouts,
+            [
+                Brownout {
+                    start: at(2),
+                    end: Some(at(4)),
+                },
+                Brownout {
+                    start: at(5),
+                    end: Some(at(6)),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,1148 @@
+//! The field management system's side of the protocol, for running scrimmages without the official FMS.
+//!
+//! [`Fms`] takes the place of the field: each driver station connects over TCP and sends its team number,
+//! is told which station it's at, and from then on is sent a [`ControlPacket`] every 250 ms
+//! while it reports back how its robot is doing in a [`StatusPacket`].
+//!
+//! The other way around, a [`Robot`](crate::Robot) built [`with_fms`](crate::RobotBuilder::with_fms)
+//! is the driver station, letting an FMS like this one or the official one drive it.
+
+use std::{
+    ffi::CString,
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream, UdpSocket},
+    select,
+    sync::{broadcast, mpsc::UnboundedSender, RwLock},
+    task::{JoinHandle, JoinSet},
+};
+
+use crate::{
+    builder::Config,
+    recv::{
+        tcp::{write_frame, Decoder, Reader, TcpParseError},
+        udp::{Battery, UdpParseError},
+    },
+    send::{
+        tcp::{truncate_for_prefix, MatchInfo, MatchType, TcpEvent},
+        udp::{self as send_udp, Control, UdpEvent},
+    },
+    timer::MatchTimer,
+    traits::Bytes,
+    Alliance, Error, Mode, State,
+};
+
+const UDP_PORT: u16 = 1160;
+const TCP_PORT: u16 = 1750;
+const DS_UDP_RX_PORT: u16 = 1121;
+
+/// Where the FMS is found on the field network.
+pub const FIELD_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 100, 5));
+
+const CONTROL_PERIOD: Duration = Duration::from_millis(250);
+// How long robots are disabled between autonomous and teleop
+const PAUSE: Duration = Duration::from_secs(3);
+// How long a status packet counts towards a station's robot being linked
+const STATUS_TIMEOUT: Duration = Duration::from_secs(2);
+// How long a driver station waits for a control packet before it's no longer under the FMS's control
+const FMS_TIMEOUT: Duration = Duration::from_secs(1);
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+const ALLIANCES: [Alliance; 6] = [
+    Alliance::Red1,
+    Alliance::Red2,
+    Alliance::Red3,
+    Alliance::Blue1,
+    Alliance::Blue2,
+    Alliance::Blue3,
+];
+
+/// What the FMS tells a driver station to do, sent over UDP.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ControlPacket {
+    pub sequence: u16,
+    pub comm_version: u8,
+    /// The mode the robot should be in and whether it's enabled or estopped.
+    pub control: Control,
+    pub station: Alliance,
+    pub level: MatchType,
+    pub match_number: u16,
+    /// How many times the match has been played, starting at `1`.
+    pub play: u8,
+    /// The field's clock, sent to the nearest microsecond.
+    pub time: SystemTime,
+    /// The seconds left in the current period.
+    pub remaining: u16,
+}
+
+impl ControlPacket {
+    // sequence + comm_version + control + request + station + level + match_number + play + date + remaining
+    const SIZE: usize = 2 + 1 + 1 + 1 + 1 + 1 + 2 + 1 + 10 + 2;
+}
+
+impl TryFrom<&[u8]> for ControlPacket {
+    type Error = UdpParseError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        if value.len() < Self::SIZE {
+            return Err(UdpParseError::InvalidLength);
+        }
+
+        let u16_at = |i: usize| u16::from_be_bytes([value[i], value[i + 1]]);
+        let date = send_udp::Tag::Date {
+            microseconds: u32::from_be_bytes([value[10], value[11], value[12], value[13]]),
+            second: value[14],
+            minute: value[15],
+            hour: value[16],
+            day: value[17],
+            month: value[18],
+            year: value[19],
+        };
+
+        Ok(ControlPacket {
+            sequence: u16_at(0),
+            comm_version: value[2],
+            control: Control::from_bits(value[3]),
+            station: Alliance::from_bits(value[5]).ok_or(UdpParseError::InvalidAlliance)?,
+            level: MatchType::try_from(value[6]).map_err(|_| UdpParseError::InvalidTag)?,
+            match_number: u16_at(7),
+            play: value[9],
+            time: date.time().unwrap_or(SystemTime::UNIX_EPOCH),
+            remaining: u16_at(20),
+        })
+    }
+}
+
+impl Bytes for ControlPacket {
+    fn write_bytes(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.sequence.to_be_bytes());
+        out.push(self.comm_version);
+        self.control.write_bytes(out);
+        // Request byte, which the FMS never sets
+        out.push(0x00);
+        self.station.write_bytes(out);
+        out.push(self.level as u8);
+        out.extend_from_slice(&self.match_number.to_be_bytes());
+        out.push(self.play);
+
+        if let send_udp::Tag::Date {
+            microseconds,
+            second,
+            minute,
+            hour,
+            day,
+            month,
+            year,
+        } = send_udp::Tag::date(self.time)
+        {
+            out.extend_from_slice(&microseconds.to_be_bytes());
+            out.extend_from_slice(&[second, minute, hour, day, month, year]);
+        }
+
+        out.extend_from_slice(&self.remaining.to_be_bytes());
+    }
+}
+
+/// What a driver station reports back to the FMS, sent over UDP.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StatusPacket {
+    pub sequence: u16,
+    pub comm_version: u8,
+    pub status: DsStatus,
+    pub team: u16,
+    pub battery: Battery,
+}
+
+impl StatusPacket {
+    // sequence + comm_version + status + team + battery
+    const SIZE: usize = 2 + 1 + 1 + 2 + 2;
+}
+
+impl TryFrom<&[u8]> for StatusPacket {
+    type Error = UdpParseError;
+
+    /// Parses a status packet, ignoring the tags the driver station sends after it.
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        if value.len() < Self::SIZE {
+            return Err(UdpParseError::InvalidLength);
+        }
+
+        Ok(StatusPacket {
+            sequence: u16::from_be_bytes([value[0], value[1]]),
+            comm_version: value[2],
+            status: DsStatus::from_bits(value[3]),
+            team: u16::from_be_bytes([value[4], value[5]]),
+            battery: Battery::from_bits([value[6], value[7]]),
+        })
+    }
+}
+
+impl Bytes for StatusPacket {
+    fn write_bytes(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.sequence.to_be_bytes());
+        out.push(self.comm_version);
+        out.push(self.status.bits());
+        out.extend_from_slice(&self.team.to_be_bytes());
+        out.extend_from_slice(&self.battery.bits());
+    }
+}
+
+/// Which parts of the robot's network a driver station can reach.
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct DsStatus(u8);
+
+impl DsStatus {
+    const ROBOT_LINKED_MASK: u8 = 0x20;
+    const RADIO_MASK: u8 = 0x10;
+    const RIO_MASK: u8 = 0x08;
+
+    pub fn from_bits(bits: u8) -> DsStatus {
+        DsStatus(bits)
+    }
+
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+
+    /// Whether the robot is answering control packets.
+    pub fn robot_linked(&self) -> bool {
+        self.0 & Self::ROBOT_LINKED_MASK != 0
+    }
+
+    pub fn with_robot_linked(self, linked: bool) -> Self {
+        self.with_mask(Self::ROBOT_LINKED_MASK, linked)
+    }
+
+    /// Whether the robot's radio answers pings.
+    pub fn radio(&self) -> bool {
+        self.0 & Self::RADIO_MASK != 0
+    }
+
+    pub fn with_radio(self, reachable: bool) -> Self {
+        self.with_mask(Self::RADIO_MASK, reachable)
+    }
+
+    /// Whether the roboRIO answers pings.
+    pub fn rio(&self) -> bool {
+        self.0 & Self::RIO_MASK != 0
+    }
+
+    pub fn with_rio(self, reachable: bool) -> Self {
+        self.with_mask(Self::RIO_MASK, reachable)
+    }
+
+    fn with_mask(mut self, mask: u8, set: bool) -> Self {
+        if set {
+            self.0 |= mask;
+        } else {
+            self.0 &= !mask;
+        }
+        self
+    }
+}
+
+/// Whether a driver station is at the station the FMS expects it at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StationStatus {
+    Good = 0,
+    /// The team is in the match, but at a different station.
+    Bad = 1,
+    /// The team isn't in the current match.
+    Waiting = 2,
+}
+
+impl TryFrom<u8> for StationStatus {
+    type Error = TcpParseError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(StationStatus::Good),
+            1 => Ok(StationStatus::Bad),
+            2 => Ok(StationStatus::Waiting),
+            _ => Err(TcpParseError::InvalidTag),
+        }
+    }
+}
+
+/// A frame sent between the FMS and a driver station over TCP.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Tag {
+    /// Sent by the driver station as soon as it connects.
+    TeamNumber(u16),
+    EventCode(String),
+    StationInfo {
+        station: Alliance,
+        status: StationStatus,
+    },
+}
+
+impl Tag {
+    /// Parses a single frame, starting with the tag ID.
+    ///
+    /// Returns `Ok(None)` for heartbeats and tags that are not understood.
+    pub fn parse(frame: &[u8]) -> Result<Option<Tag>, TcpParseError> {
+        let (id, mut data) = match frame.split_first() {
+            Some((id, data)) => (*id, Reader::new(data)),
+            None => return Ok(None),
+        };
+
+        let tag = match id {
+            0x14 => {
+                let len = data.u8()? as usize;
+                Tag::EventCode(String::from_utf8_lossy(data.bytes(len)?).into_owned())
+            }
+            0x18 => Tag::TeamNumber(data.u16()?),
+            0x19 => Tag::StationInfo {
+                station: Alliance::from_bits(data.u8()?).ok_or(TcpParseError::InvalidTag)?,
+                status: StationStatus::try_from(data.u8()?)?,
+            },
+            _ => return Ok(None),
+        };
+
+        Ok(Some(tag))
+    }
+
+    fn id(&self) -> u8 {
+        match self {
+            Tag::EventCode(_) => 0x14,
+            Tag::TeamNumber(_) => 0x18,
+            Tag::StationInfo { .. } => 0x19,
+        }
+    }
+}
+
+impl Bytes for Tag {
+    /// Writes the tag as a complete frame, prefixed with its size and ID.
+    fn write_bytes(&self, out: &mut Vec<u8>) {
+        write_frame(out, self.id(), |out| match self {
+            Tag::TeamNumber(team) => out.extend_from_slice(&team.to_be_bytes()),
+            Tag::EventCode(code) => {
+                let code = truncate_for_prefix(code.as_bytes());
+                out.push(code.len() as u8);
+                out.extend_from_slice(code);
+            }
+            Tag::StationInfo { station, status } => {
+                station.write_bytes(out);
+                out.push(*status as u8);
+            }
+        });
+    }
+}
+
+/// One of the six driver station positions, from [`Fms::stations`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Station {
+    pub station: Alliance,
+    /// The team assigned with [`Fms::assign`].
+    pub team: Option<u16>,
+    /// Set with [`Fms::estop`] until the next match is set up.
+    pub estopped: bool,
+    /// Where the assigned team's driver station is connected from.
+    pub ds: Option<SocketAddr>,
+    /// The latest status packet from the assigned team, and when it arrived.
+    pub status: Option<(StatusPacket, Instant)>,
+}
+
+impl Station {
+    fn new(station: Alliance) -> Station {
+        Station {
+            station,
+            team: None,
+            estopped: false,
+            ds: None,
+            status: None,
+        }
+    }
+
+    pub fn ds_connected(&self) -> bool {
+        self.ds.is_some()
+    }
+
+    /// Whether the driver station has recently reported that its robot is answering.
+    pub fn robot_linked(&self) -> bool {
+        self.status.is_some_and(|(status, received)| {
+            status.status.robot_linked() && received.elapsed() < STATUS_TIMEOUT
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Field {
+    stations: [Station; 6],
+    event_code: String,
+    level: MatchType,
+    match_number: u16,
+    /// The timer of the running match and when it started.
+    running: Option<(MatchTimer, Instant)>,
+    enabled: bool,
+    mode: Mode,
+}
+
+impl Field {
+    fn new() -> Field {
+        Field {
+            stations: ALLIANCES.map(Station::new),
+            event_code: String::new(),
+            level: MatchType::Practice,
+            match_number: 1,
+            running: None,
+            enabled: false,
+            mode: Mode::Autonomous,
+        }
+    }
+
+    fn station(&mut self, station: Alliance) -> &mut Station {
+        &mut self.stations[station as usize]
+    }
+
+    /// The mode every robot is in, whether they are enabled, and the time left in the period at `now`.
+    ///
+    /// A match runs autonomous, pauses disabled, then runs teleop,
+    /// leaving robots disabled once it's over.
+    fn period(&self, now: Instant) -> (Mode, bool, Duration) {
+        let Some((timer, started)) = self.running else {
+            return (self.mode, self.enabled, Duration::ZERO);
+        };
+
+        let auto = timer.duration(Mode::Autonomous).unwrap_or_default();
+        let teleop = timer.duration(Mode::Teleoperated).unwrap_or_default();
+        let elapsed = now.saturating_duration_since(started);
+
+        if elapsed < auto {
+            (Mode::Autonomous, true, auto - elapsed)
+        } else if elapsed < auto + PAUSE {
+            (Mode::Teleoperated, false, teleop)
+        } else if elapsed < auto + PAUSE + teleop {
+            (Mode::Teleoperated, true, auto + PAUSE + teleop - elapsed)
+        } else {
+            (Mode::Teleoperated, false, Duration::ZERO)
+        }
+    }
+
+    fn control_packet(&self, station: &Station, sequence: u16, now: Instant) -> ControlPacket {
+        let (mode, enabled, remaining) = self.period(now);
+
+        ControlPacket {
+            sequence,
+            comm_version: 0,
+            control: Control::default()
+                .with_mode(mode)
+                .with_enabled(enabled && !station.estopped)
+                .with_estopped(station.estopped),
+            station: station.station,
+            level: self.level,
+            match_number: self.match_number,
+            play: 1,
+            time: SystemTime::now(),
+            remaining: remaining.as_secs().min(u16::MAX as u64) as u16,
+        }
+    }
+
+    /// The station a driver station should be told it's at, claiming it for `peer` if it's assigned.
+    fn claim(&mut self, team: Option<u16>, peer: SocketAddr) -> Tag {
+        let mut info = Tag::StationInfo {
+            station: Alliance::Red1,
+            status: StationStatus::Waiting,
+        };
+
+        for station in self.stations.iter_mut() {
+            if team.is_some() && station.team == team {
+                station.ds = Some(peer);
+                info = Tag::StationInfo {
+                    station: station.station,
+                    status: StationStatus::Good,
+                };
+            } else if station.ds == Some(peer) {
+                station.ds = None;
+            }
+        }
+
+        info
+    }
+
+    fn release(&mut self, peer: SocketAddr) {
+        for station in self.stations.iter_mut() {
+            if station.ds == Some(peer) {
+                station.ds = None;
+            }
+        }
+    }
+}
+
+/// An FMS that driver stations can connect to.
+///
+/// Teams are [assigned](Fms::assign) to stations, and once a match is [set up](Fms::set_match),
+/// [`start_match`](Fms::start_match) runs every robot through autonomous and teleop together.
+#[derive(Debug)]
+pub struct Fms {
+    field: Arc<RwLock<Field>>,
+    changed: broadcast::Sender<()>,
+    udp_addr: SocketAddr,
+    tcp_addr: SocketAddr,
+    tasks: [JoinHandle<()>; 3],
+}
+
+impl Fms {
+    /// Binds the FMS's usual ports on every interface.
+    pub async fn bind() -> io::Result<Fms> {
+        Fms::bind_to(
+            Ipv4Addr::UNSPECIFIED.into(),
+            UDP_PORT,
+            TCP_PORT,
+            DS_UDP_RX_PORT,
+        )
+        .await
+    }
+
+    /// Binds the given ports on `ip`, sending control packets to driver stations on `ds_port`.
+    ///
+    /// Passing `0` for `udp_port` or `tcp_port` picks a free port,
+    /// which can be found with [`Fms::udp_addr`] and [`Fms::tcp_addr`].
+    pub async fn bind_to(
+        ip: IpAddr,
+        udp_port: u16,
+        tcp_port: u16,
+        ds_port: u16,
+    ) -> io::Result<Fms> {
+        let udp = Arc::new(UdpSocket::bind(SocketAddr::new(ip, udp_port)).await?);
+        let tcp = TcpListener::bind(SocketAddr::new(ip, tcp_port)).await?;
+        let udp_addr = udp.local_addr()?;
+        let tcp_addr = tcp.local_addr()?;
+
+        let field = Arc::new(RwLock::new(Field::new()));
+        let (changed, _) = broadcast::channel(16);
+
+        let tasks = [
+            tokio::spawn(status_task(udp.clone(), field.clone())),
+            tokio::spawn(control_task(udp, ds_port, field.clone())),
+            tokio::spawn(tcp_task(tcp, changed.clone(), field.clone())),
+        ];
+
+        Ok(Fms {
+            field,
+            changed,
+            udp_addr,
+            tcp_addr,
+            tasks,
+        })
+    }
+
+    pub fn udp_addr(&self) -> SocketAddr {
+        self.udp_addr
+    }
+
+    pub fn tcp_addr(&self) -> SocketAddr {
+        self.tcp_addr
+    }
+
+    pub async fn stations(&self) -> Vec<Station> {
+        self.field.read().await.stations.to_vec()
+    }
+
+    /// Puts `team` at `station`, moving it from any other station it was at.
+    pub async fn assign(&self, station: Alliance, team: u16) {
+        let mut field = self.field.write().await;
+        for other in field.stations.iter_mut() {
+            if other.team == Some(team) {
+                other.team = None;
+            }
+        }
+        field.station(station).team = Some(team);
+        drop(field);
+
+        let _ = self.changed.send(());
+    }
+
+    pub async fn unassign(&self, station: Alliance) {
+        self.field.write().await.station(station).team = None;
+        let _ = self.changed.send(());
+    }
+
+    /// Sets the event code sent to each driver station along with its station.
+    pub async fn set_event_code(&self, code: impl Into<String>) {
+        self.field.write().await.event_code = code.into();
+        let _ = self.changed.send(());
+    }
+
+    /// Sets up the next match, stopping any match that is running and clearing every estop.
+    pub async fn set_match(&self, level: MatchType, number: u16) {
+        let mut field = self.field.write().await;
+        field.level = level;
+        field.match_number = number;
+        field.running = None;
+        field.enabled = false;
+        for station in field.stations.iter_mut() {
+            station.estopped = false;
+        }
+    }
+
+    /// Starts the match, running autonomous, a short pause, and then teleop.
+    pub async fn start_match(&self, timer: MatchTimer) {
+        self.field.write().await.running = Some((timer, Instant::now()));
+    }
+
+    /// Stops the match early and disables every robot.
+    pub async fn abort_match(&self) {
+        let mut field = self.field.write().await;
+        field.running = None;
+        field.enabled = false;
+    }
+
+    /// Whether a match has started and not yet reached the end of teleop.
+    pub async fn match_running(&self) -> bool {
+        let field = self.field.read().await;
+        field.running.is_some_and(|(timer, started)| {
+            let length = timer.duration(Mode::Autonomous).unwrap_or_default()
+                + PAUSE
+                + timer.duration(Mode::Teleoperated).unwrap_or_default();
+            started.elapsed() < length
+        })
+    }
+
+    /// Enables or disables every robot outside of a match.
+    pub async fn set_enabled(&self, enabled: bool) {
+        self.field.write().await.enabled = enabled;
+    }
+
+    /// Sets the mode of every robot outside of a match.
+    pub async fn set_mode(&self, mode: Mode) {
+        self.field.write().await.mode = mode;
+    }
+
+    /// Estops the robot at `station` until the next match is set up.
+    pub async fn estop(&self, station: Alliance) {
+        self.field.write().await.station(station).estopped = true;
+    }
+}
+
+impl Drop for Fms {
+    fn drop(&mut self) {
+        for task in self.tasks.iter() {
+            task.abort();
+        }
+    }
+}
+
+async fn status_task(socket: Arc<UdpSocket>, field: Arc<RwLock<Field>>) {
+    let mut buf = [0u8; 1024];
+
+    loop {
+        let Ok(bytes) = socket.recv(&mut buf).await else {
+            continue;
+        };
+
+        let Ok(packet) = StatusPacket::try_from(&buf[0..bytes]) else {
+            continue;
+        };
+
+        let mut field = field.write().await;
+        if let Some(station) = field
+            .stations
+            .iter_mut()
+            .find(|station| station.team == Some(packet.team))
+        {
+            station.status = Some((packet, Instant::now()));
+        }
+    }
+}
+
+async fn control_task(socket: Arc<UdpSocket>, ds_port: u16, field: Arc<RwLock<Field>>) {
+    let mut interval = tokio::time::interval(CONTROL_PERIOD);
+    let mut sequence: u16 = 0;
+
+    loop {
+        interval.tick().await;
+        sequence = sequence.wrapping_add(1);
+
+        let now = Instant::now();
+        let packets: Vec<_> = {
+            let field = field.read().await;
+            field
+                .stations
+                .iter()
+                .filter_map(|station| {
+                    let ds = station.ds?;
+                    let mut send = Vec::new();
+                    field
+                        .control_packet(station, sequence, now)
+                        .write_bytes(&mut send);
+                    Some((SocketAddr::new(ds.ip(), ds_port), send))
+                })
+                .collect()
+        };
+
+        for (addr, send) in packets {
+            let _ = socket.send_to(&send, addr).await;
+        }
+    }
+}
+
+async fn tcp_task(
+    listener: TcpListener,
+    changed: broadcast::Sender<()>,
+    field: Arc<RwLock<Field>>,
+) {
+    // Connections are aborted along with this task when the set is dropped
+    let mut connections = JoinSet::new();
+
+    loop {
+        let Ok((conn, peer)) = listener.accept().await else {
+            continue;
+        };
+        // Forget the connections that have closed since the last one was accepted
+        while connections.try_join_next().is_some() {}
+
+        connections.spawn(tcp_connection(
+            conn,
+            peer,
+            changed.subscribe(),
+            field.clone(),
+        ));
+    }
+}
+
+async fn tcp_connection(
+    mut conn: TcpStream,
+    peer: SocketAddr,
+    mut changed: broadcast::Receiver<()>,
+    field: Arc<RwLock<Field>>,
+) {
+    let mut decoder = Decoder::new();
+    let mut buf = [0u8; 1024];
+    let mut team = None;
+    let mut sent = None;
+
+    loop {
+        select! {
+            read = conn.read(&mut buf) => match read {
+                Ok(0) | Err(_) => break,
+                Ok(bytes) => {
+                    decoder.extend(&buf[0..bytes]);
+                    while let Some(frame) = decoder.next_frame() {
+                        if let Ok(Some(Tag::TeamNumber(number))) = Tag::parse(&frame) {
+                            team = Some(number);
+                        }
+                    }
+                }
+            },
+            recv = changed.recv() => match recv {
+                Ok(()) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+        }
+
+        if team.is_none() {
+            continue;
+        }
+
+        let mut current = field.write().await;
+        let info = current.claim(team, peer);
+        let tags = (Tag::EventCode(current.event_code.clone()), info);
+        drop(current);
+
+        // Only tell the driver station when something changed
+        if sent.as_ref() != Some(&tags) {
+            let mut send = Vec::new();
+            tags.0.write_bytes(&mut send);
+            tags.1.write_bytes(&mut send);
+            if conn.write_all(&send).await.is_err() {
+                break;
+            }
+            sent = Some(tags);
+        }
+    }
+
+    field.write().await.release(peer);
+}
+
+/// What a driver station has applied from the FMS, owned by the FMS thread.
+#[derive(Debug, Default)]
+struct FmsControl {
+    event_code: Option<CString>,
+    last: Option<ControlPacket>,
+    match_info: Option<MatchInfo>,
+}
+
+impl FmsControl {
+    fn apply_tag(&mut self, tag: Tag) {
+        if let Tag::EventCode(code) = tag {
+            self.event_code = CString::new(code).ok().filter(|code| !code.is_empty());
+        }
+    }
+
+    /// The events that bring the robot in line with a control packet.
+    ///
+    /// The enabled state, mode, and alliance are sent with every packet, so the robot can't drift from the field.
+    /// An estop is only ever latched, never cleared, so the robot can still be estopped locally,
+    /// and the match time is only sent while the field has one running, so a [`MatchTimer`] set on the robot isn't overridden.
+    fn apply(&mut self, packet: ControlPacket) -> (Vec<UdpEvent>, Option<TcpEvent>) {
+        let mut events = Vec::new();
+        let last = self.last.replace(packet.clone());
+        let changed = |field: fn(&ControlPacket) -> u8| {
+            last.as_ref()
+                .is_none_or(|last| field(last) != field(&packet))
+        };
+
+        if last.is_none() {
+            events.push(UdpEvent::FmsConnected(true));
+        }
+        events.push(UdpEvent::Field {
+            enabled: packet.control.enabled(),
+            mode: packet.control.mode(),
+            alliance: packet.station,
+        });
+        if packet.control.estopped() && changed(|packet| packet.control.estopped() as u8) {
+            events.push(UdpEvent::Estopped(true));
+        }
+        let running = packet.control.enabled() || packet.remaining > 0;
+        events.push(UdpEvent::MatchTime(
+            running.then(|| Duration::from_secs(packet.remaining as u64)),
+        ));
+
+        let match_info = MatchInfo::new(self.event_code.clone(), packet.level);
+        let match_info = (self.match_info.as_ref() != Some(&match_info)).then(|| {
+            self.match_info = Some(match_info.clone());
+            TcpEvent::MatchInfo(match_info)
+        });
+
+        (events, match_info)
+    }
+
+    /// The events that take the robot back out of the FMS's control.
+    fn lose(&mut self) -> Vec<UdpEvent> {
+        self.match_info = None;
+
+        match self.last.take() {
+            Some(_) => vec![
+                UdpEvent::FmsConnected(false),
+                UdpEvent::Enabled(false),
+                UdpEvent::MatchTime(None),
+            ],
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Hands control of the robot to the FMS at `fms`, reconnecting whenever the connection drops.
+///
+/// The robot only counts as linked while it's answering control packets,
+/// since the radio and roboRIO aren't pinged separately.
+pub(crate) async fn fms_thread(
+    config: Config,
+    fms: IpAddr,
+    socket: std::net::UdpSocket,
+    team: u16,
+    state: Arc<RwLock<State>>,
+    udp_tx: UnboundedSender<UdpEvent>,
+    tcp_tx: UnboundedSender<TcpEvent>,
+) -> Result<(), Error> {
+    let socket = UdpSocket::from_std(socket)?;
+    let status_addr = SocketAddr::new(fms, config.fms_udp_port);
+    let mut control = FmsControl::default();
+    let mut sequence: u16 = 0;
+    let mut last_udp = Instant::now();
+
+    // Queueing only fails once the robot is dropped, which stops this thread
+    let queue = |events: Vec<UdpEvent>| events.into_iter().all(|ev| udp_tx.send(ev).is_ok());
+
+    loop {
+        let mut conn = match TcpStream::connect((fms, config.fms_tcp_port)).await {
+            Ok(conn) => conn,
+            Err(_) => {
+                tokio::time::sleep(RECONNECT_DELAY).await;
+                continue;
+            }
+        };
+        conn.set_nodelay(true)?;
+
+        let mut send = Vec::new();
+        Tag::TeamNumber(team).write_bytes(&mut send);
+        if conn.write_all(&send).await.is_err() {
+            continue;
+        }
+
+        let mut decoder = Decoder::new();
+        let mut tcp_buf = [0u8; 1024];
+        let mut udp_buf = [0u8; 1024];
+
+        loop {
+            select! {
+                read = conn.read(&mut tcp_buf) => match read {
+                    Ok(0) | Err(_) => break,
+                    Ok(bytes) => {
+                        decoder.extend(&tcp_buf[0..bytes]);
+                        while let Some(frame) = decoder.next_frame() {
+                            if let Ok(Some(tag)) = Tag::parse(&frame) {
+                                control.apply_tag(tag);
+                            }
+                        }
+                    }
+                },
+                // Timed from the last control packet, so TCP traffic can't keep the FMS connected
+                recv = tokio::time::timeout_at(
+                    (last_udp + FMS_TIMEOUT).into(),
+                    socket.recv_from(&mut udp_buf),
+                ) => {
+                    let (bytes, addr) = match recv {
+                        Ok(Ok(recv)) => recv,
+                        Ok(Err(_)) => continue,
+                        Err(_) => {
+                            // Rearms the timeout rather than losing the FMS over and over
+                            last_udp = Instant::now();
+                            state.write().await.fms_connected = false;
+                            if !queue(control.lose()) {
+                                return Ok(());
+                            }
+                            continue;
+                        }
+                    };
+                    if addr.ip() != fms {
+                        continue;
+                    }
+                    let Ok(packet) = ControlPacket::try_from(&udp_buf[0..bytes]) else {
+                        continue;
+                    };
+                    last_udp = Instant::now();
+
+                    let comm_version = packet.comm_version;
+                    let (events, match_info) = control.apply(packet);
+                    if let Some(ev) = match_info {
+                        // The TCP thread only stops once the robot is dropped
+                        let _ = tcp_tx.send(ev);
+                    }
+                    if !queue(events) {
+                        return Ok(());
+                    }
+
+                    let mut current_state = state.write().await;
+                    current_state.fms_connected = true;
+                    let linked = current_state.connected;
+                    let battery = Battery::from_voltage(current_state.battery);
+                    drop(current_state);
+
+                    let mut send = Vec::new();
+                    StatusPacket {
+                        sequence,
+                        comm_version,
+                        status: DsStatus::default()
+                            .with_robot_linked(linked)
+                            .with_radio(linked)
+                            .with_rio(linked),
+                        team,
+                        battery,
+                    }
+                    .write_bytes(&mut send);
+                    let _ = socket.send_to(&send, status_addr).await;
+                    sequence = sequence.wrapping_add(1);
+                }
+            }
+        }
+
+        state.write().await.fms_connected = false;
+        if !queue(control.lose()) {
+            return Ok(());
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{until, within};
+
+    #[test]
+    fn packets_round_trip() {
+        let control = ControlPacket {
+            sequence: 12,
+            comm_version: 0,
+            control: Control::default()
+                .with_mode(Mode::Autonomous)
+                .with_enabled(true),
+            station: Alliance::Blue2,
+            level: MatchType::Qualifications,
+            match_number: 42,
+            play: 1,
+            time: SystemTime::UNIX_EPOCH + Duration::from_micros(1_760_000_000_123_456),
+            remaining: 14,
+        };
+        let mut out = Vec::new();
+        control.write_bytes(&mut out);
+        assert_eq!(out.len(), ControlPacket::SIZE);
+        assert_eq!(ControlPacket::try_from(out.as_slice()), Ok(control));
+
+        let status = StatusPacket {
+            sequence: 3,
+            comm_version: 0,
+            status: DsStatus::default().with_robot_linked(true).with_rio(true),
+            team: 1234,
+            battery: Battery::from_voltage(12.5),
+        };
+        let mut out = Vec::new();
+        status.write_bytes(&mut out);
+        assert_eq!(StatusPacket::try_from(out.as_slice()), Ok(status));
+
+        for tag in [
+            Tag::TeamNumber(1234),
+            Tag::EventCode("2026test".to_owned()),
+            Tag::StationInfo {
+                station: Alliance::Red3,
+                status: StationStatus::Waiting,
+            },
+        ] {
+            let mut decoder = Decoder::new();
+            let mut out = Vec::new();
+            tag.write_bytes(&mut out);
+            decoder.extend(&out);
+            assert_eq!(Tag::parse(&decoder.next_frame().unwrap()), Ok(Some(tag)));
+        }
+    }
+
+    #[test]
+    fn control_never_clears_estop() {
+        let packet = |estopped, remaining| ControlPacket {
+            sequence: 0,
+            comm_version: 0,
+            control: Control::default().with_estopped(estopped),
+            station: Alliance::Red1,
+            level: MatchType::Practice,
+            match_number: 1,
+            play: 1,
+            time: SystemTime::UNIX_EPOCH,
+            remaining,
+        };
+        let estops = |events: &[UdpEvent]| {
+            events
+                .iter()
+                .filter_map(|event| match event {
+                    UdpEvent::Estopped(estopped) => Some(*estopped),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let match_time = |events: &[UdpEvent]| {
+            events.iter().find_map(|event| match event {
+                UdpEvent::MatchTime(remaining) => Some(*remaining),
+                _ => None,
+            })
+        };
+
+        let mut control = FmsControl::default();
+        let (events, _) = control.apply(packet(false, 0));
+        assert_eq!(estops(&events), []);
+        assert_eq!(match_time(&events), Some(None));
+
+        let (events, _) = control.apply(packet(true, 15));
+        assert_eq!(estops(&events), [true]);
+        assert_eq!(match_time(&events), Some(Some(Duration::from_secs(15))));
+
+        let (events, _) = control.apply(packet(false, 0));
+        assert_eq!(estops(&events), []);
+    }
+
+    #[test]
+    fn match_periods() {
+        let mut field = Field::new();
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert_eq!(
+            field.period(at(0)),
+            (Mode::Autonomous, false, Duration::ZERO)
+        );
+
+        field.running = Some((MatchTimer::default(), start));
+        assert_eq!(
+            field.period(at(5)),
+            (Mode::Autonomous, true, Duration::from_secs(10))
+        );
+        assert_eq!(
+            field.period(at(16)),
+            (Mode::Teleoperated, false, Duration::from_secs(135))
+        );
+        assert_eq!(
+            field.period(at(28)),
+            (Mode::Teleoperated, true, Duration::from_secs(125))
+        );
+        assert_eq!(
+            field.period(at(200)),
+            (Mode::Teleoperated, false, Duration::ZERO)
+        );
+
+        field.station(Alliance::Red2).estopped = true;
+        let packet = field.control_packet(&field.stations[1], 1, at(5));
+        assert!(packet.control.estopped());
+        assert!(!packet.control.enabled());
+    }
+
+    #[tokio::test]
+    async fn control_station() {
+        let ds = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let ds_port = ds.local_addr().unwrap().port();
+        let fms = Fms::bind_to(Ipv4Addr::LOCALHOST.into(), 0, 0, ds_port)
+            .await
+            .unwrap();
+        fms.assign(Alliance::Blue2, 1234).await;
+
+        let mut conn = within(TcpStream::connect(fms.tcp_addr())).await.unwrap();
+        let mut send = Vec::new();
+        Tag::TeamNumber(1234).write_bytes(&mut send);
+        conn.write_all(&send).await.unwrap();
+
+        let mut decoder = Decoder::new();
+        let mut buf = [0u8; 1024];
+        let info = within(async {
+            loop {
+                if let Some(frame) = decoder.next_frame() {
+                    if let Ok(Some(tag @ Tag::StationInfo { .. })) = Tag::parse(&frame) {
+                        break tag;
+                    }
+                    continue;
+                }
+                let bytes = conn.read(&mut buf).await.unwrap();
+                decoder.extend(&buf[0..bytes]);
+            }
+        })
+        .await;
+        assert_eq!(
+            info,
+            Tag::StationInfo {
+                station: Alliance::Blue2,
+                status: StationStatus::Good
+            }
+        );
+
+        fms.estop(Alliance::Blue2).await;
+        let packet = within(async {
+            loop {
+                let bytes = ds.recv(&mut buf).await.unwrap();
+                let packet = ControlPacket::try_from(&buf[0..bytes]).unwrap();
+                if packet.control.estopped() {
+                    break packet;
+                }
+            }
+        })
+        .await;
+        assert_eq!(packet.station, Alliance::Blue2);
+
+        let mut send = Vec::new();
+        StatusPacket {
+            sequence: 1,
+            comm_version: 0,
+            status: DsStatus::default().with_robot_linked(true),
+            team: 1234,
+            battery: Battery::from_voltage(12.0),
+        }
+        .write_bytes(&mut send);
+        ds.send_to(&send, fms.udp_addr()).await.unwrap();
+
+        until(|| async { fms.stations().await[4].robot_linked() }).await;
+    }
+}
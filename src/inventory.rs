@@ -0,0 +1,292 @@
+//! The hardware the robot program has reported using, from the roboRIO's usage report.
+
+use std::mem;
+
+use crate::recv::entry::{Entry, Framework, Language};
+
+/// What the robot program is built with and which hardware it uses,
+/// from [`Robot::inventory`](crate::Robot::inventory).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HardwareInventory {
+    pub language: Option<Language>,
+    pub framework: Option<Framework>,
+    /// Motor controllers and servos driven over PWM.
+    pub pwm: Vec<Device>,
+    /// Devices on the CAN bus, by device ID.
+    pub can: Vec<Device>,
+    pub solenoids: Vec<Device>,
+    /// Sensors, with the channel they are read from when it is reported.
+    pub sensors: Vec<Device>,
+    /// Encoders and counters, by the index of the FPGA counter they use rather than a channel.
+    pub counters: Vec<Device>,
+    /// Channels that more than one device has been reported on.
+    pub conflicts: Vec<Conflict>,
+}
+
+/// A piece of hardware and the channel or ID it uses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Device {
+    /// The channel, the ID on the CAN bus, or the index of an FPGA counter.
+    pub id: Option<u8>,
+    pub entry: Entry,
+}
+
+/// Where two devices can get in each other's way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Bus {
+    Pwm,
+    Can,
+    Solenoid,
+    /// The DIO channels, shared by inputs and outputs.
+    Digital,
+}
+
+/// Several devices reported on the same channel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub bus: Bus,
+    pub id: u8,
+    pub devices: Vec<Entry>,
+}
+
+/// Where an entry belongs in the inventory.
+enum Kind {
+    Pwm(u8),
+    Can(u8),
+    Solenoid(u8),
+    Sensor(Option<u8>),
+    Counter(u8),
+    Other,
+}
+
+impl HardwareInventory {
+    /// Sorts the entries from a usage report.
+    pub fn new(entries: &[Entry]) -> HardwareInventory {
+        let mut inventory = HardwareInventory::default();
+
+        for entry in entries {
+            match kind(entry) {
+                Kind::Pwm(channel) => inventory.pwm.push(Device::new(channel, entry)),
+                Kind::Can(id) => inventory.can.push(Device::new(id, entry)),
+                Kind::Solenoid(channel) => inventory.solenoids.push(Device::new(channel, entry)),
+                Kind::Sensor(channel) => inventory.sensors.push(Device {
+                    id: channel,
+                    entry: entry.clone(),
+                }),
+                Kind::Counter(index) => inventory.counters.push(Device::new(index, entry)),
+                Kind::Other => match *entry {
+                    Entry::Language(language) => inventory.language = Some(language),
+                    Entry::Framework(framework) => inventory.framework = Some(framework),
+                    _ => {}
+                },
+            }
+        }
+
+        inventory.find_conflicts(entries);
+        inventory
+    }
+
+    fn find_conflicts(&mut self, entries: &[Entry]) {
+        let digital: Vec<_> = entries
+            .iter()
+            .filter_map(|entry| match *entry {
+                Entry::DigitalInput { channel } | Entry::DigitalOutput { channel } => {
+                    Some(Device::new(channel, entry))
+                }
+                _ => None,
+            })
+            .collect();
+
+        let mut conflicts = Vec::new();
+        conflicts.extend(conflicts_on(Bus::Pwm, &self.pwm, |_, _| true));
+        // Every kind of CAN device has its own set of IDs
+        conflicts.extend(conflicts_on(Bus::Can, &self.can, |a, b| {
+            mem::discriminant(a) == mem::discriminant(b)
+        }));
+        conflicts.extend(conflicts_on(Bus::Solenoid, &self.solenoids, |_, _| true));
+        conflicts.extend(conflicts_on(Bus::Digital, &digital, |_, _| true));
+
+        self.conflicts = conflicts;
+    }
+}
+
+impl Device {
+    fn new(id: u8, entry: &Entry) -> Device {
+        Device {
+            id: Some(id),
+            entry: entry.clone(),
+        }
+    }
+}
+
+/// Groups devices that share an ID, in the order they were first reported.
+fn conflicts_on(
+    bus: Bus,
+    devices: &[Device],
+    clash: impl Fn(&Entry, &Entry) -> bool,
+) -> Vec<Conflict> {
+    let mut conflicts: Vec<Conflict> = Vec::new();
+
+    for (i, device) in devices.iter().enumerate() {
+        let Some(id) = device.id else {
+            continue;
+        };
+
+        let reported = conflicts.iter().any(|conflict| {
+            conflict.id == id
+                && conflict
+                    .devices
+                    .iter()
+                    .any(|other| clash(other, &device.entry))
+        });
+        if reported {
+            continue;
+        }
+
+        let clashing: Vec<_> = devices[(i + 1)..]
+            .iter()
+            .filter(|other| other.id == Some(id) && clash(&device.entry, &other.entry))
+            .map(|other| other.entry.clone())
+            .collect();
+
+        if !clashing.is_empty() {
+            let mut shared = vec![device.entry.clone()];
+            shared.extend(clashing);
+            conflicts.push(Conflict {
+                bus,
+                id,
+                devices: shared,
+            });
+        }
+    }
+
+    conflicts
+}
+
+fn kind(entry: &Entry) -> Kind {
+    match *entry {
+        Entry::PWM { channel }
+        | Entry::Jaguar { channel }
+        | Entry::Servo { channel }
+        | Entry::Victor { channel }
+        | Entry::Talon { channel }
+        | Entry::VictorSP { channel }
+        | Entry::PWMTalonSRC { channel }
+        | Entry::RevSPARK { channel }
+        | Entry::MindsensorsSD540 { channel }
+        | Entry::NidecBrushless { channel } => Kind::Pwm(channel),
+
+        Entry::CANTalonSRX { channel } => Kind::Can(channel),
+        Entry::PigeonIMU { id }
+        | Entry::CANifier { id }
+        | Entry::CTRE_future0 { id }
+        | Entry::CTRE_future1 { id }
+        | Entry::CTRE_future2 { id }
+        | Entry::CTRE_future3 { id } => Kind::Can(id),
+
+        Entry::Solenoid { channel } => Kind::Solenoid(channel),
+
+        Entry::Accelerometer { channel }
+        | Entry::AnalogChannel { channel }
+        | Entry::DigitalInput { channel }
+        | Entry::GearTooth { channel }
+        | Entry::Gyro { channel }
+        | Entry::Ultrasonic { channel }
+        | Entry::SRF08 { channel }
+        | Entry::DigitalFilter { channel } => Kind::Sensor(Some(channel)),
+        Entry::Encoder { fpga_index, .. } => Kind::Counter(fpga_index),
+        Entry::Counter { index, .. } => Kind::Counter(index),
+        Entry::ADXL345(_)
+        | Entry::ADXL362 { .. }
+        | Entry::ADXRS450 { .. }
+        | Entry::ADIS16448
+        | Entry::HiTechnicColorSensor
+        | Entry::HiTechnicAccel
+        | Entry::HiTechnicCompass => Kind::Sensor(None),
+
+        _ => Kind::Other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use super::*;
+    use crate::recv::entry::{CounterMode, Encoding};
+
+    #[test]
+    fn sort_usage_report() {
+        const REPORT: &CStr = c"C3W1d0s0s1>A5>J5>A5i0i0N2O2U0";
+        let inventory = HardwareInventory::new(&Entry::parse_entries(REPORT.into()));
+
+        assert_eq!(inventory.language, Some(Language::Java));
+        assert_eq!(inventory.framework, Some(Framework::Iterative));
+        assert_eq!(inventory.pwm.len(), 3);
+        assert_eq!(inventory.can.len(), 3);
+        assert_eq!(inventory.solenoids.len(), 2);
+        assert_eq!(
+            inventory.sensors,
+            [
+                Device::new(2, &Entry::DigitalInput { channel: 2 }),
+                Device::new(0, &Entry::Gyro { channel: 0 }),
+            ]
+        );
+
+        assert_eq!(
+            inventory.conflicts,
+            [
+                Conflict {
+                    bus: Bus::Pwm,
+                    id: 0,
+                    devices: vec![Entry::PWM { channel: 0 }, Entry::Talon { channel: 0 }],
+                },
+                // The Pigeon can share an ID with the Talon SRXs
+                Conflict {
+                    bus: Bus::Can,
+                    id: 5,
+                    devices: vec![
+                        Entry::CANTalonSRX { channel: 5 },
+                        Entry::CANTalonSRX { channel: 5 },
+                    ],
+                },
+                Conflict {
+                    bus: Bus::Solenoid,
+                    id: 0,
+                    devices: vec![Entry::Solenoid { channel: 0 }; 2],
+                },
+                Conflict {
+                    bus: Bus::Digital,
+                    id: 2,
+                    devices: vec![
+                        Entry::DigitalInput { channel: 2 },
+                        Entry::DigitalOutput { channel: 2 },
+                    ],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn keep_counters_off_dio_channels() {
+        let entries = [
+            Entry::DigitalInput { channel: 2 },
+            Entry::Encoder {
+                fpga_index: 2,
+                encoding: Encoding::X4,
+            },
+            Entry::Counter {
+                index: 2,
+                mode: CounterMode::TwoPulse,
+            },
+        ];
+        let inventory = HardwareInventory::new(&entries);
+
+        assert_eq!(inventory.sensors, [Device::new(2, &entries[0])]);
+        assert_eq!(
+            inventory.counters,
+            [Device::new(2, &entries[1]), Device::new(2, &entries[2])]
+        );
+        assert!(inventory.conflicts.is_empty());
+    }
+}
@@ -0,0 +1,438 @@
+use crate::{
+    error::Failure,
+    recv::udp,
+    send::{
+        tcp::{Joystick, JoystickType},
+        udp::{Buttons, Tag, UdpEvent},
+    },
+    Error,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::UnboundedSender;
+
+/// The number of joystick slots the robot can see.
+pub const SLOTS: usize = 6;
+
+/// A handle to the joystick slots of a [`Robot`](crate::Robot),
+/// created with [`Robot::joysticks`](crate::Robot::joysticks).
+///
+/// Descriptors are sent to the robot over TCP whenever a slot changes,
+/// and the values of every slot are sent with each UDP packet.
+/// Changes to slots past [`SLOTS`], or to axes, buttons, and POVs the
+/// joystick in a slot does not have, are ignored.
+#[derive(Debug, Clone)]
+pub struct Joysticks {
+    udp_tx: UnboundedSender<UdpEvent>,
+    failure: Failure,
+}
+
+impl Joysticks {
+    pub(crate) fn new(udp_tx: UnboundedSender<UdpEvent>, failure: Failure) -> Joysticks {
+        Joysticks { udp_tx, failure }
+    }
+
+    /// Plugs the joystick into `slot`, replacing whatever was there.
+    ///
+    /// The descriptor's index is overwritten with `slot`.
+    /// Its axes and buttons start out zeroed, and its POVs released.
+    pub fn plug(&self, slot: u8, joystick: Joystick) -> Result<(), Error> {
+        self.queue(JoystickEvent::Plug(slot, joystick))
+    }
+
+    pub fn unplug(&self, slot: u8) -> Result<(), Error> {
+        self.queue(JoystickEvent::Unplug(slot))
+    }
+
+    /// Swaps the joysticks in the two slots, along with their current values.
+    pub fn swap(&self, first: u8, second: u8) -> Result<(), Error> {
+        self.queue(JoystickEvent::Swap(first, second))
+    }
+
+    pub fn set_axis(&self, slot: u8, axis: u8, value: i8) -> Result<(), Error> {
+        self.queue(JoystickEvent::Axis { slot, axis, value })
+    }
+
+    pub fn set_button(&self, slot: u8, button: u8, pressed: bool) -> Result<(), Error> {
+        self.queue(JoystickEvent::Button {
+            slot,
+            button,
+            pressed,
+        })
+    }
+
+    /// Sets the angle of a POV in degrees, with `-1` meaning it is not pressed.
+    pub fn set_pov(&self, slot: u8, pov: u8, angle: i16) -> Result<(), Error> {
+        self.queue(JoystickEvent::Pov { slot, pov, angle })
+    }
+
+    fn queue(&self, ev: JoystickEvent) -> Result<(), Error> {
+        self.udp_tx
+            .send(UdpEvent::Joystick(ev))
+            .map_err(|_| self.failure.stopped())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum JoystickEvent {
+    Plug(u8, Joystick),
+    Unplug(u8),
+    Swap(u8, u8),
+    Axis { slot: u8, axis: u8, value: i8 },
+    Button { slot: u8, button: u8, pressed: bool },
+    Pov { slot: u8, pov: u8, angle: i16 },
+}
+
+/// The outputs the robot program set on a joystick,
+/// through `GenericHID.setOutputs` and `GenericHID.setRumble`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Output {
+    /// 1 bit per output, stored LSB 0
+    pub outputs: u32,
+    pub left_rumble: u16,
+    pub right_rumble: u16,
+}
+
+impl Output {
+    /// Whether the output is on, numbered from 0.
+    pub fn output(&self, n: u8) -> bool {
+        n < 32 && (self.outputs >> n) & 1 == 1
+    }
+
+    /// The left rumble strength, from 0 to 1.
+    pub fn left_rumble_strength(&self) -> f32 {
+        self.left_rumble as f32 / u16::MAX as f32
+    }
+
+    /// The right rumble strength, from 0 to 1.
+    pub fn right_rumble_strength(&self) -> f32 {
+        self.right_rumble as f32 / u16::MAX as f32
+    }
+}
+
+/// Drives real controllers from the outputs the robot program sets,
+/// added with [`Robot::add_output_sink`](crate::Robot::add_output_sink).
+///
+/// Sinks are called from their own task, so one that falls behind only skips outputs,
+/// but they must not block, as that task shares a runtime with the connection.
+pub trait OutputSink: Send + 'static {
+    /// Called whenever the output of the joystick in `slot` changes.
+    ///
+    /// Every slot is reset to the default output when the robot disconnects,
+    /// so rumble motors are never left running.
+    fn set_output(&mut self, slot: u8, output: Output);
+}
+
+impl<F> OutputSink for F
+where
+    F: FnMut(u8, Output) + Send + 'static,
+{
+    fn set_output(&mut self, slot: u8, output: Output) {
+        self(slot, output)
+    }
+}
+
+/// Finds the slot each output tag is for.
+///
+/// The robot sends an output tag for each joystick in slot order,
+/// the same order joystick tags are sent in.
+pub(crate) fn outputs(tags: &[udp::Tag]) -> impl Iterator<Item = (u8, Output)> + '_ {
+    tags.iter()
+        .filter_map(|tag| match *tag {
+            udp::Tag::JoystickOutput {
+                outputs,
+                left_rumble,
+                right_rumble,
+            } => Some(Output {
+                outputs,
+                left_rumble,
+                right_rumble,
+            }),
+            _ => None,
+        })
+        .take(SLOTS)
+        .enumerate()
+        .map(|(slot, output)| (slot as u8, output))
+}
+
+/// The descriptors and live values of every slot, owned by the UDP thread.
+#[derive(Debug, Default)]
+pub(crate) struct Slots {
+    slots: [Option<Slot>; SLOTS],
+}
+
+#[derive(Debug, Clone)]
+struct Slot {
+    descriptor: Joystick,
+    axes: Vec<i8>,
+    buttons: Buttons,
+    povs: Vec<i16>,
+}
+
+impl Slot {
+    fn new(descriptor: Joystick) -> Slot {
+        Slot {
+            // Decoded descriptors aren't capped like built ones are
+            axes: vec![
+                0;
+                descriptor
+                    .axis_types()
+                    .len()
+                    .min(Joystick::MAX_AXES as usize)
+            ],
+            buttons: Buttons::new(descriptor.button_count()),
+            povs: vec![-1; descriptor.pov_count().min(Joystick::MAX_POVS) as usize],
+            descriptor,
+        }
+    }
+}
+
+impl Slots {
+    /// Applies the event, returning the descriptors that need to be resent to the robot.
+    pub(crate) fn apply(&mut self, ev: JoystickEvent) -> Vec<Joystick> {
+        match ev {
+            JoystickEvent::Plug(slot, joystick) => {
+                let Some(entry) = self.slots.get_mut(slot as usize) else {
+                    return Vec::new();
+                };
+                let slot = Slot::new(joystick.with_index(slot));
+                let descriptor = slot.descriptor.clone();
+                *entry = Some(slot);
+
+                vec![descriptor]
+            }
+            JoystickEvent::Unplug(slot) => match self.slots.get_mut(slot as usize) {
+                Some(entry) => {
+                    *entry = None;
+                    vec![self.descriptor(slot)]
+                }
+                None => Vec::new(),
+            },
+            JoystickEvent::Swap(first, second) => {
+                if first as usize >= SLOTS || second as usize >= SLOTS || first == second {
+                    return Vec::new();
+                }
+
+                self.slots.swap(first as usize, second as usize);
+                for index in [first, second] {
+                    if let Some(slot) = self.slots[index as usize].take() {
+                        self.slots[index as usize] = Some(Slot {
+                            descriptor: slot.descriptor.with_index(index),
+                            ..slot
+                        });
+                    }
+                }
+
+                vec![self.descriptor(first), self.descriptor(second)]
+            }
+            JoystickEvent::Axis { slot, axis, value } => {
+                if let Some(axis) = self
+                    .get_mut(slot)
+                    .and_then(|s| s.axes.get_mut(axis as usize))
+                {
+                    *axis = value;
+                }
+
+                Vec::new()
+            }
+            JoystickEvent::Button {
+                slot,
+                button,
+                pressed,
+            } => {
+                if let Some(slot) = self.get_mut(slot) {
+                    if button < slot.buttons.len() {
+                        slot.buttons.set_button(button, pressed);
+                    }
+                }
+
+                Vec::new()
+            }
+            JoystickEvent::Pov { slot, pov, angle } => {
+                if let Some(pov) = self
+                    .get_mut(slot)
+                    .and_then(|s| s.povs.get_mut(pov as usize))
+                {
+                    *pov = angle;
+                }
+
+                Vec::new()
+            }
+        }
+    }
+
+    /// The joystick tags to send in a UDP packet.
+    ///
+    /// The robot assigns indices by the order of the tags,
+    /// so empty slots before the last plugged in joystick are sent without any values.
+    pub(crate) fn tags(&self) -> Vec<Tag> {
+        let used = self
+            .slots
+            .iter()
+            .rposition(Option::is_some)
+            .map_or(0, |last| last + 1);
+
+        self.slots[..used]
+            .iter()
+            .map(|slot| match slot {
+                Some(slot) => Tag::Joystick {
+                    axes: slot.axes.clone(),
+                    buttons: slot.buttons.clone(),
+                    povs: slot.povs.clone(),
+                },
+                None => Tag::Joystick {
+                    axes: Vec::new(),
+                    buttons: Buttons::new(0),
+                    povs: Vec::new(),
+                },
+            })
+            .collect()
+    }
+
+    fn get_mut(&mut self, slot: u8) -> Option<&mut Slot> {
+        self.slots.get_mut(slot as usize)?.as_mut()
+    }
+
+    /// The descriptor of the joystick in the slot,
+    /// or an unknown joystick without any axes, buttons, or POVs if it is empty.
+    fn descriptor(&self, index: u8) -> Joystick {
+        match self.slots[index as usize] {
+            Some(ref slot) => slot.descriptor.clone(),
+            None => Joystick::new(index, JoystickType::Unknown, Default::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::send::tcp::AxisType;
+    use crate::traits::Bytes;
+
+    fn gamepad() -> Joystick {
+        Joystick::new(0, JoystickType::XInputGamepad, c"Gamepad".into())
+            .with_axes(vec![AxisType::X, AxisType::Y])
+            .with_button_count(10)
+            .with_pov_count(1)
+    }
+
+    #[test]
+    fn swap_reindexes_descriptors() {
+        let mut slots = Slots::default();
+        slots.apply(JoystickEvent::Plug(0, gamepad()));
+        slots.apply(JoystickEvent::Axis {
+            slot: 0,
+            axis: 1,
+            value: -128,
+        });
+
+        let descriptors = slots.apply(JoystickEvent::Swap(0, 2));
+        assert_eq!(descriptors[0].index(), 0);
+        assert_eq!(descriptors[0].ty(), JoystickType::Unknown);
+        assert_eq!(descriptors[1].index(), 2);
+        assert_eq!(descriptors[1].name().as_bytes(), b"Gamepad");
+
+        let tags = slots.tags();
+        assert_eq!(tags.len(), 3);
+        assert!(matches!(&tags[2], Tag::Joystick { axes, .. } if *axes == [0, -128]));
+    }
+
+    #[test]
+    fn find_output_slots() {
+        let output = |left_rumble| udp::Tag::JoystickOutput {
+            outputs: 0b101,
+            left_rumble,
+            right_rumble: 0,
+        };
+        let tags = [
+            output(0),
+            udp::Tag::DiskInfo { free_space: 0 },
+            output(u16::MAX),
+        ];
+
+        let found: Vec<_> = outputs(&tags).collect();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[1].0, 1);
+        assert_eq!(found[1].1.left_rumble_strength(), 1.0);
+        assert!(found[1].1.output(2) && !found[1].1.output(1));
+    }
+
+    #[test]
+    fn limit_buttons_to_bitfield() {
+        let mut slots = Slots::default();
+        let joystick =
+            Joystick::new(0, JoystickType::HIDJoystick, c"Panel".into()).with_button_count(100);
+        assert_eq!(joystick.button_count(), Buttons::MAX_COUNT);
+
+        slots.apply(JoystickEvent::Plug(0, joystick));
+        for button in [63, 64, 99] {
+            slots.apply(JoystickEvent::Button {
+                slot: 0,
+                button,
+                pressed: true,
+            });
+        }
+
+        match &slots.tags()[0] {
+            Tag::Joystick { buttons, .. } => {
+                assert_eq!(buttons.len(), Buttons::MAX_COUNT);
+                assert!(buttons.button(63));
+            }
+            tag => panic!("unexpected tag: {tag:?}"),
+        }
+    }
+
+    #[test]
+    fn limit_axes_and_povs() {
+        let mut slots = Slots::default();
+        let joystick = Joystick::new(0, JoystickType::HIDGamepad, c"Pad".into())
+            .with_axes(vec![AxisType::X; 20])
+            .with_axis(AxisType::Y)
+            .with_pov_count(200);
+        assert_eq!(joystick.axis_types().len(), Joystick::MAX_AXES as usize);
+        assert_eq!(joystick.pov_count(), Joystick::MAX_POVS);
+
+        slots.apply(JoystickEvent::Plug(0, joystick));
+        let tag = slots.tags().remove(0);
+        match &tag {
+            Tag::Joystick { axes, povs, .. } => {
+                assert_eq!(axes.len(), Joystick::MAX_AXES as usize);
+                assert_eq!(povs.len(), Joystick::MAX_POVS as usize);
+            }
+            tag => panic!("unexpected tag: {tag:?}"),
+        }
+
+        // The tag's size still fits in its one byte
+        let mut out = Vec::new();
+        tag.write_bytes(&mut out);
+        assert_eq!(out[0] as usize, out.len() - 1);
+    }
+
+    #[test]
+    fn write_joystick_tags() {
+        let mut slots = Slots::default();
+        slots.apply(JoystickEvent::Plug(1, gamepad()));
+        slots.apply(JoystickEvent::Button {
+            slot: 1,
+            button: 9,
+            pressed: true,
+        });
+        slots.apply(JoystickEvent::Pov {
+            slot: 1,
+            pov: 0,
+            angle: 90,
+        });
+
+        let mut out = Vec::new();
+        for tag in slots.tags() {
+            tag.write_bytes(&mut out);
+        }
+
+        assert_eq!(
+            out,
+            [
+                0x04, 0x0c, 0x00, 0x00, 0x00, // Empty slot 0
+                0x0a, 0x0c, 0x02, 0x00, 0x00, 0x0a, 0x02, 0x00, 0x01, 0x00, 0x5a, // Slot 1
+            ]
+        );
+    }
+}
@@ -9,100 +9,314 @@ pub mod send {
     pub mod udp;
 }
 
+pub mod address;
+mod builder;
+pub mod console;
+pub mod diagnostics;
+mod error;
+pub mod event;
+pub mod faults;
+pub mod fms;
+pub mod inventory;
+pub mod joystick;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+#[cfg(feature = "pcap")]
+pub mod pcap;
+pub mod practice;
+pub mod radio;
+pub mod robot;
 mod sync;
+#[cfg(test)]
+mod test_util;
+pub mod timer;
 pub mod traits;
-
+pub mod versions;
+
+pub use address::{ConnectionTarget, RobotAddress};
+use builder::Config;
+pub use builder::RobotBuilder;
+use console::{Console, Message, CONSOLE_CAPACITY};
+use diagnostics::Diagnostics;
+pub use error::Error;
+use error::Failure;
+use event::{Event, Tracker, EVENTS_CAPACITY};
+use faults::FaultLog;
+use inventory::HardwareInventory;
+use joystick::{Joysticks, Output, OutputSink, Slots, SLOTS};
+use practice::{PracticeMatch, PracticeRun, PracticeSlot};
+use radio::{Radio, RadioStatus};
+use recv::tcp::{Decoder, Tag, TcpResponse};
 use recv::udp::{CodeStatus, UdpResponse};
 use send::tcp::{self, MatchInfo, MatchType, TcpEvent};
 use send::udp;
 use send::udp::UdpEvent;
 use serde::{Deserialize, Serialize};
-use std::net::SocketAddr;
+use std::future::Future;
+use std::mem;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
-use std::task::Poll;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
+use timer::{Countdown, MatchTimer};
 use tokio::net::{TcpStream, UdpSocket};
 pub use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
 use tokio::{
-    io::{self, AsyncWriteExt},
+    io::{AsyncReadExt, AsyncWriteExt},
     select,
 };
 use traits::Bytes;
-
-const UDP_PORT: u16 = 1110;
-const TCP_PORT: u16 = 1740;
-const SIM_IP: [u8; 4] = [127, 0, 0, 1];
+use versions::Versions;
 
 // There's probably an IP address that DriverStation connects from
 const DS_UDP_IP: [u8; 4] = [0, 0, 0, 0];
-const DS_SIM_UDP_IP: [u8; 4] = [127, 0, 0, 1];
-const DS_UDP_TX_PORT: u16 = 56789;
-const DS_UDP_RX_PORT: u16 = 1150;
 
+const ERRORS_CAPACITY: usize = 64;
+
+/// A connection to an FRC robot, kept up by background tasks.
 #[derive(Debug)]
 pub struct Robot {
     state: Arc<RwLock<State>>,
+    diagnostics: Arc<RwLock<Diagnostics>>,
+    faults: Arc<RwLock<FaultLog>>,
+    inventory: Arc<RwLock<Option<HardwareInventory>>>,
+    versions: Arc<RwLock<Versions>>,
+    expected_ssid: Option<String>,
     tcp_tx: UnboundedSender<TcpEvent>,
     udp_tx: UnboundedSender<UdpEvent>,
+    publishers: Publishers,
+    failure: Failure,
+    practice: PracticeSlot,
+    // Owns the runtime when `Robot` is created outside of one
     rt: sync::Runtime,
 }
 
 impl Robot {
+    /// Connects to the team's robot wherever it can be found.
+    ///
+    /// See [`ConnectionTarget::team`] for the addresses that are tried.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the driver station's ports can't be bound, see [`Robot::try_new`].
     pub fn new(team_number: u16) -> Self {
-        let team_ip = ip_from_team(team_number);
+        RobotBuilder::new(team_number).build()
+    }
+
+    /// Connects to the team's robot wherever it can be found,
+    /// or fails if the driver station's ports can't be bound.
+    pub fn try_new(team_number: u16) -> Result<Self, Error> {
+        RobotBuilder::new(team_number).try_build()
+    }
 
+    /// Connects to whichever of the target's candidates answers first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the driver station's ports can't be bound.
+    pub fn new_with_target(team_number: u16, target: ConnectionTarget) -> Self {
+        RobotBuilder::new(team_number).with_target(target).build()
+    }
+
+    /// Configures the connection to the robot before making it.
+    pub fn builder(team_number: u16) -> RobotBuilder {
+        RobotBuilder::new(team_number)
+    }
+
+    fn spawn(team_number: u16, target: ConnectionTarget, config: Config) -> Result<Self, Error> {
         let state = Arc::new(RwLock::new(State::new(team_number)));
+        let diagnostics = Arc::new(RwLock::new(Diagnostics::default()));
+        let faults = Arc::new(RwLock::new(FaultLog::default()));
+        let inventory = Arc::new(RwLock::new(None));
+        let versions = Arc::new(RwLock::new(Versions::default()));
         let (conn_tx, conn_rx) = unbounded_channel();
 
-        let rt = sync::Runtime::current().unwrap();
+        let rt = sync::Runtime::current()?;
+
+        // Bound up front so ports that are already in use are reported to the caller
+        let sockets = bind_udp(config)?;
+        let fms_socket = config.fms.map(|_| bind_fms(config)).transpose()?;
+
+        let publishers = Publishers::new();
+        let failure = Failure::default();
 
         let (tcp_tx, tcp_rx) = unbounded_channel();
-        rt.spawn(tcp_thread(team_ip, state.clone(), tcp_rx, conn_rx));
+        rt.spawn(supervise(
+            tcp_thread(
+                config,
+                tcp_rx,
+                conn_rx,
+                state.clone(),
+                faults.clone(),
+                inventory.clone(),
+                versions.clone(),
+                publishers.clone(),
+            ),
+            publishers.clone(),
+            failure.clone(),
+        ));
 
         let (udp_tx, udp_rx) = unbounded_channel();
-        rt.spawn(udp_thread(team_ip, state.clone(), udp_rx, conn_tx));
-
-        tcp_tx.send(TcpEvent::GameData(GameData::empty())).unwrap();
-        tcp_tx
-            .send(TcpEvent::MatchInfo(MatchInfo::new(None, MatchType::None)))
-            .unwrap();
+        rt.spawn(supervise(
+            udp_thread(
+                config,
+                sockets,
+                target,
+                state.clone(),
+                diagnostics.clone(),
+                faults.clone(),
+                udp_rx,
+                conn_tx,
+                tcp_tx.clone(),
+                publishers.clone(),
+            ),
+            publishers.clone(),
+            failure.clone(),
+        ));
+
+        if let (Some(fms), Some(socket)) = (config.fms, fms_socket) {
+            rt.spawn(supervise(
+                fms::fms_thread(
+                    config,
+                    fms,
+                    socket,
+                    team_number,
+                    state.clone(),
+                    udp_tx.clone(),
+                    tcp_tx.clone(),
+                ),
+                publishers.clone(),
+                failure.clone(),
+            ));
+        }
 
-        Robot {
+        let robot = Robot {
             state,
+            diagnostics,
+            faults,
+            inventory,
+            versions,
+            expected_ssid: None,
             tcp_tx,
             udp_tx,
+            publishers,
+            failure,
+            practice: PracticeSlot::default(),
             rt,
-        }
+        };
+
+        robot.queue_tcp(TcpEvent::GameData(GameData::empty()))?;
+        robot.queue_tcp(TcpEvent::MatchInfo(MatchInfo::new(None, MatchType::None)))?;
+
+        Ok(robot)
+    }
+
+    /// Gets a handle to plug in joysticks and update their values.
+    pub fn joysticks(&self) -> Joysticks {
+        Joysticks::new(self.udp_tx.clone(), self.failure.clone())
+    }
+
+    /// Drives the sink with the outputs and rumble the robot program sets on each joystick.
+    ///
+    /// The sink is called from its own task on the robot's runtime for as long as
+    /// the background tasks keep running, which dropping the robot does not stop.
+    /// It must not block, since it shares the runtime with the tasks talking to the robot.
+    pub fn add_output_sink<S: OutputSink>(&self, mut sink: S) {
+        let mut outputs = self.publishers.outputs.subscribe();
+
+        self.rt.spawn(async move {
+            loop {
+                match outputs.recv().await {
+                    Ok((slot, output)) => sink.set_output(slot, output),
+                    // Only the latest output matters, so missed ones are skipped
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            }
+        });
+    }
+
+    /// Subscribes to changes in the robot's state,
+    /// as they are seen in the robot's responses.
+    ///
+    /// Only events after subscribing will be seen.
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.publishers.events.subscribe()
+    }
+
+    /// Subscribes to errors from the background tasks,
+    /// such as packets from the robot that couldn't be parsed.
+    ///
+    /// Only errors after subscribing will be seen.
+    /// Use [`Robot::failure`] to find out why the tasks stopped.
+    pub fn errors(&self) -> broadcast::Receiver<Error> {
+        self.publishers.errors.subscribe()
+    }
+
+    /// The error a background task stopped with, if any.
+    ///
+    /// Once a task has stopped, queueing events fails with this error.
+    pub fn failure(&self) -> Option<Error> {
+        self.failure.get()
+    }
+
+    /// Subscribes to the robot program's standard output, errors, and warnings.
+    ///
+    /// Only messages received after the [`Console`] is created will be seen.
+    pub fn console(&self) -> Console {
+        Console::new(self.publishers.console.subscribe())
+    }
+
+    /// Enables or disables the robot, unless an FMS is connected and in control of it.
+    pub fn set_enabled(&self, enabled: bool) -> Result<(), Error> {
+        self.queue_udp(UdpEvent::Enabled(enabled))
+    }
+
+    pub fn set_estopped(&self, estopped: bool) -> Result<(), Error> {
+        self.queue_udp(UdpEvent::Estopped(estopped))
+    }
+
+    /// Ignored while an FMS is connected.
+    pub fn set_mode(&self, mode: Mode) -> Result<(), Error> {
+        self.queue_udp(UdpEvent::Mode(mode))
     }
 
-    pub fn set_enabled(&self, enabled: bool) {
-        self.queue_udp(UdpEvent::Enabled(enabled));
+    /// Ignored while an FMS is connected.
+    pub fn set_alliance(&self, alliance: Alliance) -> Result<(), Error> {
+        self.queue_udp(UdpEvent::Alliance(alliance))
     }
 
-    pub fn set_estopped(&self, estopped: bool) {
-        self.queue_udp(UdpEvent::Estopped(estopped));
+    pub fn set_team_number(&self, team_number: u16) -> Result<(), Error> {
+        self.queue_udp(UdpEvent::TeamNumber(team_number))?;
+        self.queue_tcp(TcpEvent::TeamNumber)
     }
 
-    pub fn set_mode(&self, mode: Mode) {
-        self.queue_udp(UdpEvent::Mode(mode));
+    /// Asks the roboRIO to report its software and firmware versions again, see [`Robot::versions`].
+    pub fn request_versions(&self) -> Result<(), Error> {
+        self.queue_tcp(TcpEvent::RequestVersions)
     }
 
-    pub fn set_alliance(&self, alliance: Alliance) {
-        self.queue_udp(UdpEvent::Alliance(alliance));
+    /// Runs a practice match in its own task, changing the robot's mode and enabling it for each period.
+    ///
+    /// A practice match that is already running is cancelled, and this one starts once it has stopped.
+    pub fn start_practice_match(&self, practice: PracticeMatch) -> PracticeRun {
+        let (cancel, run) = self.practice.start(practice, self.udp_tx.clone());
+        let task = self.rt.spawn(run);
+
+        PracticeRun::new(cancel, task)
     }
 
-    pub fn set_team_number(&self, team_number: u16) {
-        self.queue_udp(UdpEvent::TeamNumber(team_number));
-        self.queue_tcp(TcpEvent::TeamNumber);
+    /// Counts down each period of the match while the robot is enabled, or stops counting with `None`.
+    pub fn set_match_timer(&self, timer: Option<MatchTimer>) -> Result<(), Error> {
+        self.queue_udp(UdpEvent::MatchTimer(timer))
     }
 
-    pub fn queue_tcp(&self, ev: TcpEvent) {
-        self.tcp_tx.send(ev).unwrap();
+    pub fn queue_tcp(&self, ev: TcpEvent) -> Result<(), Error> {
+        self.tcp_tx.send(ev).map_err(|_| self.failure.stopped())
     }
 
-    pub fn queue_udp(&self, ev: UdpEvent) {
-        self.udp_tx.send(ev).unwrap();
+    pub fn queue_udp(&self, ev: UdpEvent) -> Result<(), Error> {
+        self.udp_tx.send(ev).map_err(|_| self.failure.stopped())
     }
 }
 
@@ -144,6 +358,50 @@ impl Robot {
         self.rt.block_on(self._state())
     }
 
+    /// The outputs the robot program set on the joystick in each slot.
+    pub fn joystick_outputs(&self) -> [Output; SLOTS] {
+        self.rt.block_on(self._joystick_outputs())
+    }
+
+    pub fn diagnostics(&self) -> Diagnostics {
+        self.rt.block_on(self._diagnostics())
+    }
+
+    /// The brownouts and faults seen since the robot was created.
+    pub fn fault_log(&self) -> FaultLog {
+        self.rt.block_on(self._fault_log())
+    }
+
+    /// The hardware the robot program reported using, or `None` until the roboRIO sends its usage report.
+    pub fn inventory(&self) -> Option<HardwareInventory> {
+        self.rt.block_on(self._inventory())
+    }
+
+    /// The software and firmware versions the roboRIO reported.
+    pub fn versions(&self) -> Versions {
+        self.rt.block_on(self._versions())
+    }
+
+    /// The latest radio event the roboRIO reported, cleared when the robot disconnects.
+    pub fn radio(&self) -> Option<Radio> {
+        self.rt.block_on(self._radio())
+    }
+
+    /// Whether the robot is reachable on the SSID given to [`RobotBuilder::with_expected_ssid`].
+    pub fn radio_status(&self) -> RadioStatus {
+        self.rt.block_on(self._radio_status())
+    }
+
+    /// The time left in the current period, if a [`MatchTimer`] is counting it down.
+    pub fn match_time(&self) -> Option<Duration> {
+        self.rt.block_on(self._match_time())
+    }
+
+    /// Whether an FMS is controlling the robot, see [`RobotBuilder::with_fms`].
+    pub fn fms_connected(&self) -> bool {
+        self.rt.block_on(self._fms_connected())
+    }
+
     async fn _connected(&self) -> bool {
         self.state.read().await.connected
     }
@@ -177,7 +435,48 @@ impl Robot {
     }
 
     async fn _state(&self) -> State {
-        *self.state.read().await
+        self.state.read().await.clone()
+    }
+
+    async fn _joystick_outputs(&self) -> [Output; SLOTS] {
+        self.state.read().await.joystick_outputs
+    }
+
+    async fn _diagnostics(&self) -> Diagnostics {
+        *self.diagnostics.read().await
+    }
+
+    async fn _fault_log(&self) -> FaultLog {
+        self.faults.read().await.clone()
+    }
+
+    async fn _inventory(&self) -> Option<HardwareInventory> {
+        self.inventory.read().await.clone()
+    }
+
+    async fn _versions(&self) -> Versions {
+        self.versions.read().await.clone()
+    }
+
+    async fn _match_time(&self) -> Option<Duration> {
+        self.state.read().await.match_time
+    }
+
+    async fn _fms_connected(&self) -> bool {
+        self.state.read().await.fms_connected
+    }
+
+    async fn _radio(&self) -> Option<Radio> {
+        self.state.read().await.radio.clone()
+    }
+
+    async fn _radio_status(&self) -> RadioStatus {
+        let state = self.state.read().await;
+        if !state.connected {
+            return RadioStatus::Unreachable;
+        }
+
+        Radio::status(state.radio.as_ref(), self.expected_ssid.as_deref())
     }
 }
 
@@ -216,11 +515,60 @@ impl Robot {
     }
 
     pub async fn state(&self) -> State {
-        *self.state.read().await
+        self.state.read().await.clone()
+    }
+
+    /// The outputs the robot program set on the joystick in each slot.
+    pub async fn joystick_outputs(&self) -> [Output; SLOTS] {
+        self.state.read().await.joystick_outputs
+    }
+
+    pub async fn diagnostics(&self) -> Diagnostics {
+        *self.diagnostics.read().await
+    }
+
+    /// The brownouts and faults seen since the robot was created.
+    pub async fn fault_log(&self) -> FaultLog {
+        self.faults.read().await.clone()
+    }
+
+    /// The hardware the robot program reported using, or `None` until the roboRIO sends its usage report.
+    pub async fn inventory(&self) -> Option<HardwareInventory> {
+        self.inventory.read().await.clone()
+    }
+
+    /// The software and firmware versions the roboRIO reported.
+    pub async fn versions(&self) -> Versions {
+        self.versions.read().await.clone()
+    }
+
+    /// The time left in the current period, if a [`MatchTimer`] is counting it down.
+    pub async fn match_time(&self) -> Option<Duration> {
+        self.state.read().await.match_time
+    }
+
+    /// Whether an FMS is controlling the robot, see [`RobotBuilder::with_fms`].
+    pub async fn fms_connected(&self) -> bool {
+        self.state.read().await.fms_connected
+    }
+
+    /// The latest radio event the roboRIO reported, cleared when the robot disconnects.
+    pub async fn radio(&self) -> Option<Radio> {
+        self.state.read().await.radio.clone()
+    }
+
+    /// Whether the robot is reachable on the SSID given to [`RobotBuilder::with_expected_ssid`].
+    pub async fn radio_status(&self) -> RadioStatus {
+        let state = self.state.read().await;
+        if !state.connected {
+            return RadioStatus::Unreachable;
+        }
+
+        Radio::status(state.radio.as_ref(), self.expected_ssid.as_deref())
     }
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct State {
     connected: bool,
     team: u16,
@@ -231,6 +579,10 @@ pub struct State {
     game_data: GameData,
     code: CodeStatus,
     battery: f32,
+    joystick_outputs: [Output; SLOTS],
+    radio: Option<Radio>,
+    match_time: Option<Duration>,
+    fms_connected: bool,
 }
 
 impl State {
@@ -245,46 +597,97 @@ impl State {
             game_data: GameData::default(),
             code: CodeStatus::Initializing,
             battery: 0.0,
+            joystick_outputs: Default::default(),
+            radio: None,
+            match_time: None,
+            fms_connected: false,
         }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn tcp_thread(
-    team_ip: [u8; 4],
-    state: Arc<RwLock<State>>,
+    config: Config,
     mut rx: UnboundedReceiver<TcpEvent>,
     mut conn_rx: UnboundedReceiver<Option<SocketAddr>>,
-) -> std::io::Result<()> {
+    state: Arc<RwLock<State>>,
+    faults: Arc<RwLock<FaultLog>>,
+    inventory: Arc<RwLock<Option<HardwareInventory>>>,
+    versions: Arc<RwLock<Versions>>,
+    publishers: Publishers,
+) -> Result<(), Error> {
     let mut game_data = None;
     let mut match_info = None;
     let mut joysticks = Vec::new();
+    // The latest descriptor of every slot, resent on every new connection
+    let mut descriptors: Vec<tcp::Joystick> = Vec::new();
+
+    // Set when reconnecting to the same robot
+    let mut reconnect = None;
 
     loop {
-        let mut addr = match conn_rx.recv().await {
-            Some(location) => match location {
-                Some(addr) => addr,
-                None => continue,
+        let mut addr = match reconnect.take() {
+            Some(addr) => addr,
+            None => match conn_rx.recv().await {
+                Some(location) => match location {
+                    Some(addr) => addr,
+                    None => continue,
+                },
+                None => return Ok(()),
             },
-            None => return Ok(()),
         };
-        addr.set_port(TCP_PORT);
+        addr.set_port(config.tcp_port);
 
-        let mut conn = match TcpStream::connect(addr).await {
+        let conn = match TcpStream::connect(addr).await {
             Ok(conn) => conn,
             Err(_) => continue,
         };
         conn.set_nodelay(true)?;
+        joysticks.clone_from(&descriptors);
+
+        let (mut reader, mut writer) = conn.into_split();
+        let mut decoder = Decoder::new();
+        let mut buf = [0u8; 1024];
+
+        let mut interval = tokio::time::interval(config.tcp_period);
 
         'conn: loop {
-            let start = Instant::now();
+            select! {
+                _ = interval.tick() => {},
+                read = reader.read(&mut buf) => match read {
+                    Ok(0) | Err(_) => break,
+                    Ok(bytes) => {
+                        decoder.extend(&buf[0..bytes]);
+                        loop {
+                            match decoder.decode() {
+                                Ok(Some(response)) => {
+                                    handle_tcp_response(response, &state, &faults, &inventory, &versions, &publishers).await
+                                }
+                                Ok(None) => break,
+                                // The bad frame has already been dropped
+                                Err(err) => publishers.error(err.into()),
+                            }
+                        }
+                        continue;
+                    }
+                },
+            }
 
             while let Ok(ev) = rx.try_recv() {
                 match ev {
                     TcpEvent::Exit => return Ok(()),
                     TcpEvent::GameData(gd) => game_data = Some(gd),
                     TcpEvent::MatchInfo(mi) => match_info = Some(mi),
-                    TcpEvent::Joystick(js) => joysticks.push(js),
+                    TcpEvent::Joystick(js) => {
+                        descriptors.retain(|descriptor| descriptor.index() != js.index());
+                        descriptors.push(js.clone());
+                        joysticks.push(js);
+                    }
                     TcpEvent::TeamNumber => continue 'conn,
+                    TcpEvent::RequestVersions => {
+                        reconnect = Some(addr);
+                        break 'conn;
+                    }
                 }
             }
 
@@ -301,25 +704,132 @@ async fn tcp_thread(
 
             let mut send = Vec::new();
             packet.write_bytes(&mut send);
-            if conn.write_all(&send).await.is_err() {
+            if writer.write_all(&send).await.is_err() {
                 break;
             }
+        }
+
+        // The next robot may be running a different program
+        if reconnect.is_none() {
+            *inventory.write().await = None;
+            *versions.write().await = Versions::default();
+        }
+    }
+}
 
-            // Sends a TCP packet every second
-            std::thread::sleep(Duration::from_secs(1) - Instant::now().duration_since(start));
+async fn handle_tcp_response(
+    response: TcpResponse,
+    state: &RwLock<State>,
+    faults: &RwLock<FaultLog>,
+    inventory: &RwLock<Option<HardwareInventory>>,
+    versions: &RwLock<Versions>,
+    publishers: &Publishers,
+) {
+    let now = SystemTime::now();
+    let mut fault_log = faults.write().await;
+    for tag in response.tags.iter() {
+        fault_log.apply(tag, now);
+    }
+    // `udp_thread` takes the fault log while holding the state, so let go of it first
+    drop(fault_log);
+
+    for tag in response.tags.iter() {
+        match tag {
+            Tag::UsageReport { entries, .. } => {
+                *inventory.write().await = Some(HardwareInventory::new(entries));
+            }
+            Tag::VersionInfo { .. } => versions.write().await.apply(tag),
+            Tag::Radio(event) => state.write().await.radio = Some(Radio::new(event.clone())),
+            _ => {}
         }
     }
+
+    for tag in response.tags {
+        if let Some(message) = Message::from_tag(tag) {
+            publishers.console(message);
+        }
+    }
+}
+
+/// Runs a background task, keeping the error it stops with.
+async fn supervise<F>(task: F, publishers: Publishers, failure: Failure)
+where
+    F: Future<Output = Result<(), Error>>,
+{
+    if let Err(err) = task.await {
+        failure.set(err.clone());
+        publishers.error(err);
+    }
+}
+
+/// The channels the background tasks publish to, which [`Robot`] hands out subscriptions to.
+#[derive(Debug, Clone)]
+struct Publishers {
+    console: broadcast::Sender<Message>,
+    errors: broadcast::Sender<Error>,
+    events: broadcast::Sender<Event>,
+    outputs: broadcast::Sender<(u8, Output)>,
+}
+
+// Sending only fails when nobody is subscribed, so the results are ignored
+impl Publishers {
+    fn new() -> Publishers {
+        Publishers {
+            console: broadcast::channel(CONSOLE_CAPACITY).0,
+            errors: broadcast::channel(ERRORS_CAPACITY).0,
+            events: broadcast::channel(EVENTS_CAPACITY).0,
+            outputs: broadcast::channel(EVENTS_CAPACITY).0,
+        }
+    }
+
+    fn console(&self, message: Message) {
+        let _ = self.console.send(message);
+    }
+
+    fn error(&self, err: Error) {
+        let _ = self.errors.send(err);
+    }
+
+    fn event(&self, event: Event) {
+        let _ = self.events.send(event);
+    }
+
+    fn output(&self, slot: u8, output: Output) {
+        let _ = self.outputs.send((slot, output));
+    }
+}
+
+fn bind_udp(config: Config) -> std::io::Result<(std::net::UdpSocket, std::net::UdpSocket)> {
+    let udp_tx = std::net::UdpSocket::bind(SocketAddr::from((DS_UDP_IP, config.ds_udp_tx_port)))?;
+    let udp_rx = std::net::UdpSocket::bind(SocketAddr::from((DS_UDP_IP, config.ds_udp_rx_port)))?;
+    udp_tx.set_nonblocking(true)?;
+    udp_rx.set_nonblocking(true)?;
+
+    Ok((udp_tx, udp_rx))
+}
+
+fn bind_fms(config: Config) -> std::io::Result<std::net::UdpSocket> {
+    let socket = std::net::UdpSocket::bind(SocketAddr::from((DS_UDP_IP, config.ds_fms_rx_port)))?;
+    socket.set_nonblocking(true)?;
+
+    Ok(socket)
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn udp_thread(
-    team_ip: [u8; 4],
+    config: Config,
+    sockets: (std::net::UdpSocket, std::net::UdpSocket),
+    mut target: ConnectionTarget,
     state: Arc<RwLock<State>>,
+    diagnostics: Arc<RwLock<Diagnostics>>,
+    faults: Arc<RwLock<FaultLog>>,
     mut rx: UnboundedReceiver<UdpEvent>,
     conn_tx: UnboundedSender<Option<SocketAddr>>,
-) -> std::io::Result<()> {
-    let mut team_addr = SocketAddr::from((team_ip, UDP_PORT));
-    #[cfg(not(target_os = "linux"))]
-    let sim_addr = SocketAddr::from((SIM_IP, UDP_PORT));
+    tcp_tx: UnboundedSender<TcpEvent>,
+    publishers: Publishers,
+) -> Result<(), Error> {
+    let udp_tx = UdpSocket::from_std(sockets.0)?;
+    let udp_rx = UdpSocket::from_std(sockets.1)?;
 
     let mut sequence: u16 = 0x0001;
 
@@ -330,16 +840,17 @@ async fn udp_thread(
     let mut mode = Mode::Teleoperated;
     let mut restarting_code = false;
     let mut tags = Vec::new();
+    let mut joysticks = Slots::default();
+    let mut tracker = Tracker::new(config.low_battery);
+    let mut countdown = Countdown::new(config.match_timer);
+    // Answered in the next packet once the robot asks for it
+    let mut send_date = false;
 
     'conn: loop {
-        let udp_tx = UdpSocket::bind(SocketAddr::from((DS_UDP_IP, DS_UDP_TX_PORT))).await?;
-        let udp_rx = UdpSocket::bind(SocketAddr::from((DS_UDP_IP, DS_UDP_RX_PORT))).await?;
-
-        udp_tx.connect(team_addr).await?;
-        
-        #[cfg(not(target_os = "linux"))]
-        udp_tx.connect(sim_addr).await?;
-        
+        // Every candidate is sent to until one of them answers
+        let candidates = target.resolve().await;
+        let mut robot_ip: Option<IpAddr> = None;
+
         let mut last = Instant::now();
 
         let mut rebooting_roborio = false;
@@ -349,25 +860,61 @@ async fn udp_thread(
 
             while let Ok(ev) = rx.try_recv() {
                 match ev {
-                    UdpEvent::Enabled(e) => enabled = e,
+                    // The FMS is in control of the robot while it's connected, apart from estopping it
+                    UdpEvent::Enabled(e) if !fms_connected => enabled = e,
+                    UdpEvent::Mode(m) if !fms_connected => mode = m,
+                    UdpEvent::Alliance(a) if !fms_connected => alliance = a,
+                    UdpEvent::Enabled(_) | UdpEvent::Mode(_) | UdpEvent::Alliance(_) => {}
+                    UdpEvent::Field {
+                        enabled: e,
+                        mode: m,
+                        alliance: a,
+                    } => {
+                        enabled = e;
+                        mode = m;
+                        alliance = a;
+                    }
                     UdpEvent::Estopped(e) => estopped = e,
                     UdpEvent::FmsConnected(fc) => fms_connected = fc,
-                    UdpEvent::Alliance(a) => alliance = a,
-                    UdpEvent::Mode(m) => mode = m,
                     UdpEvent::Tag(tag) => tags.push(tag),
+                    UdpEvent::MatchTimer(timer) => countdown.set_timer(timer),
+                    UdpEvent::PracticeTimer(timer) => countdown.set_practice_timer(timer),
+                    UdpEvent::MatchTime(remaining) => {
+                        countdown.set_fms_end(remaining.map(|remaining| Instant::now() + remaining))
+                    }
+                    UdpEvent::Joystick(ev) => {
+                        for descriptor in joysticks.apply(ev) {
+                            // The TCP thread only stops once the robot is dropped
+                            let _ = tcp_tx.send(TcpEvent::Joystick(descriptor));
+                        }
+                    }
                     UdpEvent::RestartCode => restarting_code = true,
                     UdpEvent::RebootRoborio => rebooting_roborio = true,
                     UdpEvent::TeamNumber(num) => {
-                        team_addr = SocketAddr::from((ip_from_team(num), UDP_PORT));
+                        target = target.with_team(num);
                         continue 'conn;
                     }
                 }
             }
 
-            let mut send_tags = Vec::new();
+            let mut send_tags = joysticks.tags();
             send_tags.append(&mut tags);
 
-            let connected = state.read().await.connected;
+            // Always in UTC rather than the system's timezone, which the standard library can't look up
+            if mem::take(&mut send_date) {
+                send_tags.push(udp::Tag::date(SystemTime::now()));
+                send_tags.push(udp::Tag::utc());
+            }
+
+            let match_time = countdown.update(enabled, mode, start);
+            if let Some(remaining) = match_time {
+                send_tags.push(udp::Tag::Countdown(remaining.as_secs_f32()));
+            }
+
+            let mut current_state = state.write().await;
+            current_state.match_time = match_time;
+            let connected = current_state.connected;
+            drop(current_state);
 
             let packet = udp::Packet::default()
                 .with_sequence(sequence)
@@ -383,44 +930,113 @@ async fn udp_thread(
 
             let mut send = Vec::new();
             packet.write_bytes(&mut send);
-            udp_tx.send(&send).await?;
+            let ips = match robot_ip {
+                Some(ref ip) => std::slice::from_ref(ip),
+                None => candidates.as_slice(),
+            };
+            for ip in ips {
+                // Candidates that can't be reached are expected while searching
+                let _ = udp_tx
+                    .send_to(&send, SocketAddr::new(*ip, config.udp_port))
+                    .await;
+            }
             sequence = sequence.wrapping_add(1);
 
-            let mut buf = [0u8; 100];
-            match udp_rx.try_recv_from(&mut buf) {
-                Ok((bytes, addr)) => {
-                    if let Ok(packet) = UdpResponse::try_from(&buf[0..bytes]) {
-                        conn_tx.send(Some(addr)).unwrap();
-                        last = Instant::now();
-                        let mut current_state = state.write().await;
+            let mut buf = [0u8; 1024];
+            // Only the robot that answered first is listened to once it's found
+            let expected =
+                |ip: IpAddr| robot_ip.map_or(candidates.contains(&ip), |robot| robot == ip);
+            let response = match udp_rx.try_recv_from(&mut buf) {
+                Ok((bytes, addr)) if expected(addr.ip()) => {
+                    match UdpResponse::try_from(&buf[0..bytes]) {
+                        Ok(packet) => Some((packet, addr)),
+                        Err(err) => {
+                            publishers.error(err.into());
+                            None
+                        }
+                    }
+                }
+                _ => None,
+            };
+
+            match response {
+                Some((packet, addr)) => {
+                    robot_ip = Some(addr.ip());
+                    send_date = packet.first_conn;
+                    // The TCP thread has already reported why it stopped
+                    let _ = conn_tx.send(Some(addr));
+                    last = Instant::now();
+                    let mut current_state = state.write().await;
+
+                    let mut fault_log = faults.write().await;
+                    if !current_state.connected {
+                        fault_log.connect(SystemTime::now());
+                    }
+                    fault_log.set_brownout(packet.status.brownout(), SystemTime::now());
+                    drop(fault_log);
+
+                    current_state.connected = true;
+                    current_state.enabled = packet.status.enabled();
+                    current_state.estopped = packet.status.estopped();
+                    current_state.mode = packet.status.mode();
+                    current_state.code = packet.trace.robot_code();
+                    current_state.battery = packet.battery.voltage();
+
+                    let mut changed_outputs = Vec::new();
+                    for (slot, output) in joystick::outputs(&packet.tags) {
+                        let current = &mut current_state.joystick_outputs[slot as usize];
+                        if *current != output {
+                            *current = output;
+                            changed_outputs.push((slot, output));
+                        }
+                    }
+                    drop(current_state);
+
+                    let mut current_diagnostics = diagnostics.write().await;
+                    for tag in packet.tags.iter() {
+                        current_diagnostics.apply(tag, last);
+                    }
+                    drop(current_diagnostics);
 
-                        current_state.connected = true;
-                        current_state.enabled = packet.status.enabled();
-                        current_state.estopped = packet.status.estopped();
-                        current_state.mode = packet.status.mode();
-                        current_state.code = packet.trace.robot_code();
-                        current_state.battery = packet.battery.voltage();
-                    } else if let Err(err) = UdpResponse::try_from(&buf[0..bytes]) {
-                        println!("{err:?}");
+                    // Published once the state is updated, so subscribers see the change
+                    for event in tracker.update(&packet) {
+                        publishers.event(event);
+                    }
+                    for (slot, output) in changed_outputs {
+                        publishers.output(slot, output);
                     }
                 }
-                Err(err) => {
-                    if last.elapsed() > Duration::from_millis(500) {
+                None => {
+                    if last.elapsed() > config.timeout {
                         // clear all state fields
                         let mut current_state = state.write().await;
                         let team = current_state.team;
+                        let outputs = current_state.joystick_outputs;
+                        // The FMS is still there without the robot
+                        let fms_connected = current_state.fms_connected;
                         *current_state = State::new(team);
-
-                        conn_tx.send(None).unwrap();
+                        current_state.fms_connected = fms_connected;
+                        drop(current_state);
+
+                        // Stops any rumble the robot program left running
+                        for (slot, output) in outputs.into_iter().enumerate() {
+                            if output != Output::default() {
+                                publishers.output(slot as u8, Output::default());
+                            }
+                        }
+                        *diagnostics.write().await = Diagnostics::default();
+                        faults.write().await.disconnect(SystemTime::now());
+
+                        if let Some(event) = tracker.disconnect() {
+                            publishers.event(event);
+                        }
+                        let _ = conn_tx.send(None);
                         break;
                     }
                 }
             }
 
-            let duration = Instant::now().duration_since(start);
-            if duration < Duration::from_millis(20) {
-                std::thread::sleep(Duration::from_millis(20) - start.elapsed());
-            }
+            tokio::time::sleep_until((start + config.udp_period).into()).await;
         }
     }
 }
@@ -462,6 +1078,20 @@ pub enum Alliance {
     Blue3,
 }
 
+impl Alliance {
+    const fn from_bits(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Alliance::Red1),
+            1 => Some(Alliance::Red2),
+            2 => Some(Alliance::Red3),
+            3 => Some(Alliance::Blue1),
+            4 => Some(Alliance::Blue2),
+            5 => Some(Alliance::Blue3),
+            _ => None,
+        }
+    }
+}
+
 impl Bytes for Alliance {
     fn write_bytes(&self, out: &mut Vec<u8>) {
         let byte = match self {
@@ -477,7 +1107,7 @@ impl Bytes for Alliance {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
 pub struct GameData {
     chars: [Option<u8>; 3],
 }
@@ -505,12 +1135,14 @@ impl GameData {
         }
     }
 
-    fn len(&self) -> u8 {
-        match self.chars {
-            [Some(_), Some(_), Some(_)] => 3,
-            [Some(_), Some(_), _] => 2,
-            [Some(_), _, _] => 1,
-            _ => 0,
+    /// Reads up to three characters, or `None` if there are more.
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        match *bytes {
+            [] => Some(GameData::empty()),
+            [first] => Some(GameData::single(first)),
+            [first, second] => Some(GameData::double(first, second)),
+            [first, second, third] => Some(GameData::triple(first, second, third)),
+            _ => None,
         }
     }
 }
@@ -528,15 +1160,191 @@ impl Bytes for GameData {
     }
 }
 
-impl Default for GameData {
-    fn default() -> Self {
-        GameData {
-            chars: [None, None, None],
+#[cfg(all(test, not(feature = "sync")))]
+mod tests {
+    use std::{net::Ipv4Addr, time::Duration};
+
+    use super::*;
+    use crate::{fms::Fms, mock::MockRobot, test_util::until};
+
+    fn free_udp_port() -> u16 {
+        std::net::UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))
+            .and_then(|socket| socket.local_addr())
+            .unwrap()
+            .port()
+    }
+
+    #[tokio::test]
+    async fn connect_to_mock_robot() {
+        let ds_port = free_udp_port();
+        let mock = MockRobot::bind_to(0, 0, ds_port).await.unwrap();
+
+        let robot = Robot::builder(8891)
+            .with_target(ConnectionTarget::new().with_address(RobotAddress::Simulation))
+            .with_udp_port(mock.udp_addr().port())
+            .with_tcp_port(mock.tcp_addr().port())
+            .with_ds_udp_tx_port(0)
+            .with_ds_udp_rx_port(ds_port)
+            .with_tcp_period(Duration::from_millis(50))
+            .build();
+        let mut events = robot.subscribe();
+
+        until(|| robot.connected()).await;
+
+        robot.set_mode(Mode::Autonomous).unwrap();
+        robot.set_enabled(true).unwrap();
+        until(|| async { robot.enabled().await && robot.mode().await == Mode::Autonomous }).await;
+
+        let mut seen = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            seen.push(event);
         }
+        assert_eq!(seen.first(), Some(&Event::Connected));
+        assert!(seen.contains(&Event::Enabled));
+        assert!(seen.contains(&Event::ModeChanged(Mode::Autonomous)));
+
+        until(|| async { mock.received().await.tcp_connections == 1 }).await;
+        let received = mock.received().await;
+        assert!(received
+            .udp
+            .windows(2)
+            .all(|packets| { packets[1].sequence() == packets[0].sequence().wrapping_add(1) }));
+    }
+
+    #[tokio::test]
+    async fn relay_tcp_through_mock_robot() {
+        let ds_port = free_udp_port();
+        let mock = MockRobot::bind_to(0, 0, ds_port).await.unwrap();
+
+        let robot = Robot::builder(8891)
+            .with_target(ConnectionTarget::new().with_address(RobotAddress::Simulation))
+            .with_udp_port(mock.udp_addr().port())
+            .with_tcp_port(mock.tcp_addr().port())
+            .with_ds_udp_tx_port(0)
+            .with_ds_udp_rx_port(ds_port)
+            .with_tcp_period(Duration::from_millis(50))
+            .build();
+        let mut console = robot.console();
+
+        robot
+            .queue_tcp(TcpEvent::GameData(GameData::single(b'L')))
+            .unwrap();
+        until(|| async { mock.received().await.tcp.contains(&vec![0x0e, b'L']) }).await;
+
+        assert!(mock.send_tcp(&Tag::StandardOutput {
+            timestamp: 1.5,
+            sequence: 3,
+            message: c"Hello".into(),
+        }));
+        let message = tokio::time::timeout(Duration::from_secs(5), console.recv())
+            .await
+            .expect("timed out waiting on the console");
+        assert_eq!(
+            message,
+            Some(Message::Output {
+                timestamp: 1.5,
+                sequence: 3,
+                message: "Hello".to_owned(),
+            })
+        );
     }
-}
 
-/// Constructs the RoboRIO IP address from the given team number.
-fn ip_from_team(team: u16) -> [u8; 4] {
-    [10, (team / 100) as u8, (team % 100) as u8, 2]
+    #[tokio::test]
+    async fn follow_the_fms() {
+        let ds_port = free_udp_port();
+        let ds_fms_port = free_udp_port();
+        let mock = MockRobot::bind_to(0, 0, ds_port).await.unwrap();
+        let fms = Fms::bind_to(Ipv4Addr::LOCALHOST.into(), 0, 0, ds_fms_port)
+            .await
+            .unwrap();
+        fms.assign(Alliance::Blue1, 8891).await;
+        fms.set_match(MatchType::Qualifications, 12).await;
+        fms.set_mode(Mode::Autonomous).await;
+        fms.set_enabled(true).await;
+
+        let robot = Robot::builder(8891)
+            .with_target(ConnectionTarget::new().with_address(RobotAddress::Simulation))
+            .with_udp_port(mock.udp_addr().port())
+            .with_tcp_port(mock.tcp_addr().port())
+            .with_ds_udp_tx_port(0)
+            .with_ds_udp_rx_port(ds_port)
+            .with_fms(Ipv4Addr::LOCALHOST.into())
+            .with_fms_tcp_port(fms.tcp_addr().port())
+            .with_fms_udp_port(fms.udp_addr().port())
+            .with_ds_fms_rx_port(ds_fms_port)
+            .build();
+
+        until(|| async { robot.fms_connected().await && robot.enabled().await }).await;
+        assert_eq!(robot.mode().await, Mode::Autonomous);
+        until(|| async {
+            mock.received().await.udp.last().is_some_and(|packet| {
+                packet.control().fms_connected() && packet.alliance() == Alliance::Blue1
+            })
+        })
+        .await;
+
+        // The robot's status makes it back to the FMS
+        until(|| async { fms.stations().await[3].robot_linked() }).await;
+
+        fms.estop(Alliance::Blue1).await;
+        until(|| robot.estopped()).await;
+    }
+
+    #[tokio::test]
+    async fn stay_disabled_under_the_fms() {
+        let ds_port = free_udp_port();
+        let ds_fms_port = free_udp_port();
+        let mock = MockRobot::bind_to(0, 0, ds_port).await.unwrap();
+        let fms = Fms::bind_to(Ipv4Addr::LOCALHOST.into(), 0, 0, ds_fms_port)
+            .await
+            .unwrap();
+        fms.assign(Alliance::Red2, 8891).await;
+
+        let robot = Robot::builder(8891)
+            .with_target(ConnectionTarget::new().with_address(RobotAddress::Simulation))
+            .with_udp_port(mock.udp_addr().port())
+            .with_tcp_port(mock.tcp_addr().port())
+            .with_ds_udp_tx_port(0)
+            .with_ds_udp_rx_port(ds_port)
+            .with_fms(Ipv4Addr::LOCALHOST.into())
+            .with_fms_tcp_port(fms.tcp_addr().port())
+            .with_fms_udp_port(fms.udp_addr().port())
+            .with_ds_fms_rx_port(ds_fms_port)
+            .build();
+        until(|| async {
+            mock.received()
+                .await
+                .udp
+                .last()
+                .is_some_and(|packet| packet.control().fms_connected())
+        })
+        .await;
+
+        robot.set_enabled(true).unwrap();
+        robot.set_alliance(Alliance::Blue3).unwrap();
+        mock.clear().await;
+        until(|| async { mock.received().await.udp.len() >= 10 }).await;
+
+        for packet in mock.received().await.udp {
+            assert!(!packet.control().enabled());
+            assert_eq!(packet.alliance(), Alliance::Red2);
+        }
+        assert!(!robot.enabled().await);
+
+        // Estopping locally still works
+        robot.set_estopped(true).unwrap();
+        until(|| robot.estopped()).await;
+    }
+
+    #[tokio::test]
+    async fn report_ports_in_use() {
+        let taken = std::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).unwrap();
+
+        let robot = Robot::builder(8891)
+            .with_ds_udp_tx_port(0)
+            .with_ds_udp_rx_port(taken.local_addr().unwrap().port())
+            .try_build();
+
+        assert!(matches!(robot, Err(Error::Io(_))));
+    }
 }
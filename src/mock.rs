@@ -0,0 +1,228 @@
+//! A fake roboRIO for exercising a [`Robot`](crate::Robot) without any hardware.
+//!
+//! [`MockRobot`] binds the robot's side of the UDP and TCP connections on localhost,
+//! answers every control packet with a configurable [`Reply`],
+//! and records everything the driver station sent so tests can make assertions on it.
+
+use std::{
+    io,
+    net::{Ipv4Addr, SocketAddr},
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    recv::tcp::{self, TcpParseError},
+    robot::{Roborio, Tap, DS_UDP_RX_PORT, TCP_PORT, UDP_PORT},
+    send::{tcp as send_tcp, udp::Packet},
+};
+
+const MOCK_IP: Ipv4Addr = Ipv4Addr::LOCALHOST;
+
+pub use crate::robot::Reply;
+
+/// Everything the mock robot has received from the driver station.
+#[derive(Debug, Clone, Default)]
+pub struct Received {
+    /// Every control packet, in the order they arrived.
+    pub udp: Vec<Packet>,
+    /// Every non-empty TCP frame without its size prefix, in the order they arrived.
+    pub tcp: Vec<Vec<u8>>,
+    /// The number of TCP connections the driver station has made.
+    pub tcp_connections: usize,
+}
+
+impl Received {
+    /// Decodes every TCP frame, each into a packet holding the one tag it carried.
+    pub fn tcp_packets(&self) -> Result<Vec<send_tcp::Packet>, TcpParseError> {
+        self.tcp
+            .iter()
+            .map(|frame| send_tcp::Packet::from_frame(frame))
+            .collect()
+    }
+}
+
+#[derive(Debug, Default)]
+struct Recorder(Mutex<Received>);
+
+impl Tap for Recorder {
+    fn udp(&self, packet: &Packet) {
+        self.0.lock().unwrap().udp.push(packet.clone());
+    }
+
+    fn tcp_connection(&self) {
+        self.0.lock().unwrap().tcp_connections += 1;
+    }
+
+    fn tcp_frame(&self, frame: &[u8]) {
+        if !frame.is_empty() {
+            self.0.lock().unwrap().tcp.push(frame.to_vec());
+        }
+    }
+}
+
+/// A [`Roborio`] on localhost that records everything it receives.
+#[derive(Debug)]
+pub struct MockRobot {
+    roborio: Roborio,
+    received: Arc<Recorder>,
+}
+
+impl MockRobot {
+    /// Binds the roboRIO's usual ports on localhost.
+    pub async fn bind() -> io::Result<MockRobot> {
+        MockRobot::bind_to(UDP_PORT, TCP_PORT, DS_UDP_RX_PORT).await
+    }
+
+    /// Binds the given ports on localhost, replying to the driver station on `ds_port`.
+    ///
+    /// Passing `0` for `udp_port` or `tcp_port` picks a free port,
+    /// which can be found with [`MockRobot::udp_addr`] and [`MockRobot::tcp_addr`].
+    pub async fn bind_to(udp_port: u16, tcp_port: u16, ds_port: u16) -> io::Result<MockRobot> {
+        let received = Arc::new(Recorder::default());
+        let roborio = Roborio::bind_tapped(
+            MOCK_IP.into(),
+            udp_port,
+            tcp_port,
+            ds_port,
+            Some(received.clone()),
+        )
+        .await?;
+
+        Ok(MockRobot { roborio, received })
+    }
+
+    pub fn udp_addr(&self) -> SocketAddr {
+        self.roborio.udp_addr()
+    }
+
+    pub fn tcp_addr(&self) -> SocketAddr {
+        self.roborio.tcp_addr()
+    }
+
+    /// Changes how every following control packet is answered.
+    pub async fn set_reply(&self, reply: Reply) {
+        self.roborio.set_reply(reply).await;
+    }
+
+    pub async fn received(&self) -> Received {
+        self.received.0.lock().unwrap().clone()
+    }
+
+    /// Forgets everything received so far.
+    pub async fn clear(&self) {
+        *self.received.0.lock().unwrap() = Received::default();
+    }
+
+    /// Sends a tag to every connected driver station over TCP.
+    ///
+    /// Returns `false` if no driver station was connected to receive it.
+    pub fn send_tcp(&self, tag: &tcp::Tag) -> bool {
+        self.roborio.send_tcp(tag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpStream, UdpSocket},
+    };
+
+    use super::*;
+    use crate::{
+        recv::{tcp::Decoder, udp::UdpResponse},
+        send::udp as send_udp,
+        test_util::{until, within},
+        traits::Bytes,
+        GameData, Mode,
+    };
+
+    #[tokio::test]
+    async fn reply_to_control_packets() {
+        let ds = UdpSocket::bind((MOCK_IP, 0)).await.unwrap();
+        let mock = MockRobot::bind_to(0, 0, ds.local_addr().unwrap().port())
+            .await
+            .unwrap();
+        mock.set_reply(Reply::default().with_battery(11.75)).await;
+
+        let mut send = Vec::new();
+        send_udp::Packet::default()
+            .with_sequence(42)
+            .with_enabled(true)
+            .with_mode(Mode::Autonomous)
+            .with_tag(send_udp::Tag::Countdown(15.0))
+            .write_bytes(&mut send);
+        ds.send_to(&send, mock.udp_addr()).await.unwrap();
+
+        let mut buf = [0u8; 1024];
+        let bytes = within(ds.recv(&mut buf)).await.unwrap();
+        let response = UdpResponse::try_from(&buf[0..bytes]).unwrap();
+
+        assert_eq!(response.sequence, 42);
+        assert!(response.status.enabled());
+        assert_eq!(response.status.mode(), Mode::Autonomous);
+        assert!(response.trace.autonomous_mode());
+        assert_eq!(response.battery.voltage(), 11.75);
+
+        let received = mock.received().await;
+        assert_eq!(received.udp.len(), 1);
+        assert!(received.udp[0].control().enabled());
+        assert!(matches!(
+            received.udp[0].tags(),
+            [send_udp::Tag::Countdown(count)] if *count == 15.0
+        ));
+    }
+
+    #[tokio::test]
+    async fn exchange_tcp_frames() {
+        let mock = MockRobot::bind_to(0, 0, 0).await.unwrap();
+        let mut conn = within(TcpStream::connect(mock.tcp_addr())).await.unwrap();
+
+        let mut send = Vec::new();
+        send_tcp::Packet::default()
+            .with_game_data(Some(GameData::single(b'L')))
+            .write_bytes(&mut send);
+        conn.write_all(&send).await.unwrap();
+
+        until(|| async { !mock.received().await.tcp.is_empty() }).await;
+        assert_eq!(mock.received().await.tcp, [vec![0x0e, b'L']]);
+        assert_eq!(
+            mock.received().await.tcp_packets(),
+            Ok(vec![
+                send_tcp::Packet::default().with_game_data(Some(GameData::single(b'L')))
+            ])
+        );
+
+        assert!(mock.send_tcp(&tcp::Tag::StandardOutput {
+            timestamp: 1.5,
+            sequence: 3,
+            message: CString::new("Hello").unwrap(),
+        }));
+
+        let mut decoder = Decoder::new();
+        let response = within(async {
+            'read: loop {
+                let mut buf = [0u8; 1024];
+                let bytes = conn.read(&mut buf).await.unwrap();
+                decoder.extend(&buf[0..bytes]);
+
+                loop {
+                    match decoder.decode() {
+                        Ok(Some(response)) => break 'read response,
+                        Ok(None) => break,
+                        // The bad frame has already been dropped
+                        Err(_) => continue,
+                    }
+                }
+            }
+        })
+        .await;
+
+        assert!(matches!(
+            response.tags.as_slice(),
+            [tcp::Tag::StandardOutput { sequence: 3, message, .. }] if message.as_bytes() == b"Hello"
+        ));
+    }
+}
@@ -0,0 +1,221 @@
+//! A minimal pcapng reader for pulling driver station traffic out of packet captures.
+//!
+//! Only IPv4 over Ethernet is understood, which is all the driver station uses.
+//! Everything else in the capture is skipped.
+
+use std::net::SocketAddrV4;
+
+const SECTION_HEADER: u32 = 0x0A0D0D0A;
+const INTERFACE_DESCRIPTION: u32 = 0x00000001;
+const SIMPLE_PACKET: u32 = 0x00000003;
+const ENHANCED_PACKET: u32 = 0x00000006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B3C4D;
+
+const LINKTYPE_ETHERNET: u16 = 1;
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const PROTOCOL_TCP: u8 = 6;
+const PROTOCOL_UDP: u8 = 17;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PcapError {
+    InvalidLength,
+    InvalidBlock,
+    InvalidByteOrder,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Protocol {
+    Udp,
+    Tcp {
+        /// The sequence number of the first byte of the payload.
+        sequence: u32,
+    },
+}
+
+/// The payload of a single UDP datagram or TCP segment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub protocol: Protocol,
+    pub src: SocketAddrV4,
+    pub dst: SocketAddrV4,
+    pub payload: Vec<u8>,
+}
+
+impl Segment {
+    pub fn is_udp(&self) -> bool {
+        self.protocol == Protocol::Udp
+    }
+
+    pub fn is_tcp(&self) -> bool {
+        matches!(self.protocol, Protocol::Tcp { .. })
+    }
+}
+
+/// Reads every UDP and TCP payload out of a pcapng capture, in capture order.
+pub fn read(capture: &[u8]) -> Result<Vec<Segment>, PcapError> {
+    let mut segments = Vec::new();
+    let mut big_endian = false;
+    let mut link_types = Vec::new();
+
+    let mut i = 0;
+    while i < capture.len() {
+        let block = capture.get(i..(i + 12)).ok_or(PcapError::InvalidLength)?;
+        let ty = u32::from_le_bytes([block[0], block[1], block[2], block[3]]);
+
+        if ty == SECTION_HEADER {
+            big_endian = match read_u32(&block[8..12], true) {
+                BYTE_ORDER_MAGIC => true,
+                magic if magic.swap_bytes() == BYTE_ORDER_MAGIC => false,
+                _ => return Err(PcapError::InvalidByteOrder),
+            };
+            link_types.clear();
+        }
+
+        let ty = read_u32(&block[0..4], big_endian);
+        let len = read_u32(&block[4..8], big_endian) as usize;
+        if len < 12 || !len.is_multiple_of(4) {
+            return Err(PcapError::InvalidBlock);
+        }
+        let body = capture
+            .get((i + 8)..(i + len - 4))
+            .ok_or(PcapError::InvalidLength)?;
+
+        match ty {
+            INTERFACE_DESCRIPTION => {
+                let link_type = body.get(0..2).ok_or(PcapError::InvalidBlock)?;
+                link_types.push(read_u16(link_type, big_endian));
+            }
+            ENHANCED_PACKET => {
+                let header = body.get(0..20).ok_or(PcapError::InvalidBlock)?;
+                let interface = read_u32(&header[0..4], big_endian) as usize;
+                let captured = read_u32(&header[12..16], big_endian) as usize;
+                let data = body
+                    .get(20..(20 + captured))
+                    .ok_or(PcapError::InvalidBlock)?;
+
+                if link_types.get(interface) == Some(&LINKTYPE_ETHERNET) {
+                    segments.extend(parse_ethernet(data));
+                }
+            }
+            SIMPLE_PACKET => {
+                // Simple packets always come from the first interface
+                let data = body.get(4..).ok_or(PcapError::InvalidBlock)?;
+
+                if link_types.first() == Some(&LINKTYPE_ETHERNET) {
+                    segments.extend(parse_ethernet(data));
+                }
+            }
+            _ => {}
+        }
+
+        i += len;
+    }
+
+    Ok(segments)
+}
+
+/// Puts the payloads sent from `src` to `dst` over TCP back together,
+/// dropping retransmitted bytes.
+///
+/// Any bytes missing from the capture are skipped over.
+pub fn tcp_stream(segments: &[Segment], src: SocketAddrV4, dst: SocketAddrV4) -> Vec<u8> {
+    let mut ordered: Vec<(u32, &[u8])> = segments
+        .iter()
+        .filter(|segment| segment.src == src && segment.dst == dst)
+        .filter_map(|segment| match segment.protocol {
+            Protocol::Tcp { sequence } if !segment.payload.is_empty() => {
+                Some((sequence, segment.payload.as_slice()))
+            }
+            _ => None,
+        })
+        .collect();
+    ordered.sort_by_key(|(sequence, _)| *sequence);
+
+    let mut stream = Vec::new();
+    let mut next = match ordered.first() {
+        Some((sequence, _)) => *sequence,
+        None => return stream,
+    };
+
+    for (sequence, payload) in ordered {
+        let end = sequence.wrapping_add(payload.len() as u32);
+        if end <= next {
+            continue;
+        }
+
+        let skip = next.saturating_sub(sequence) as usize;
+        stream.extend_from_slice(&payload[skip..]);
+        next = end;
+    }
+
+    stream
+}
+
+fn parse_ethernet(frame: &[u8]) -> Option<Segment> {
+    let mut ethertype = u16::from_be_bytes([*frame.get(12)?, *frame.get(13)?]);
+    let mut offset = 14;
+
+    if ethertype == ETHERTYPE_VLAN {
+        ethertype = u16::from_be_bytes([*frame.get(16)?, *frame.get(17)?]);
+        offset += 4;
+    }
+
+    if ethertype != ETHERTYPE_IPV4 {
+        return None;
+    }
+
+    parse_ipv4(frame.get(offset..)?)
+}
+
+fn parse_ipv4(packet: &[u8]) -> Option<Segment> {
+    if packet.len() < 20 {
+        return None;
+    }
+
+    let header_len = ((packet[0] & 0x0F) as usize) * 4;
+    let total_len = u16::from_be_bytes([packet[2], packet[3]]) as usize;
+    let protocol = packet[9];
+    let src = [packet[12], packet[13], packet[14], packet[15]];
+    let dst = [packet[16], packet[17], packet[18], packet[19]];
+
+    // Ethernet frames may be padded past the end of the IP packet
+    let transport = packet.get(header_len..total_len.min(packet.len()))?;
+    let src_port = u16::from_be_bytes([*transport.first()?, *transport.get(1)?]);
+    let dst_port = u16::from_be_bytes([*transport.get(2)?, *transport.get(3)?]);
+
+    let (protocol, payload) = match protocol {
+        PROTOCOL_UDP => (Protocol::Udp, transport.get(8..)?),
+        PROTOCOL_TCP => {
+            let sequence = u32::from_be_bytes(transport.get(4..8)?.try_into().ok()?);
+            let data_offset = ((*transport.get(12)? >> 4) as usize) * 4;
+            (Protocol::Tcp { sequence }, transport.get(data_offset..)?)
+        }
+        _ => return None,
+    };
+
+    Some(Segment {
+        protocol,
+        src: SocketAddrV4::new(src.into(), src_port),
+        dst: SocketAddrV4::new(dst.into(), dst_port),
+        payload: payload.to_vec(),
+    })
+}
+
+fn read_u16(bytes: &[u8], big_endian: bool) -> u16 {
+    let bytes = [bytes[0], bytes[1]];
+    if big_endian {
+        u16::from_be_bytes(bytes)
+    } else {
+        u16::from_le_bytes(bytes)
+    }
+}
+
+fn read_u32(bytes: &[u8], big_endian: bool) -> u32 {
+    let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+    if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    }
+}
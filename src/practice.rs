@@ -0,0 +1,341 @@
+//! Running a practice match, like practice mode in the official driver station.
+
+use std::{
+    future::Future,
+    sync::{Arc, Mutex as StdMutex},
+    time::Duration,
+};
+
+use tokio::{
+    select,
+    sync::{mpsc::UnboundedSender, Mutex, Notify},
+    task::JoinHandle,
+};
+
+use crate::{
+    send::udp::UdpEvent,
+    timer::{MatchTimer, AUTO, TELEOP},
+    Mode,
+};
+
+const COUNTDOWN: Duration = Duration::from_secs(5);
+const DELAY: Duration = Duration::from_secs(1);
+const ENDGAME: Duration = Duration::from_secs(20);
+
+/// A part of a practice match, passed to the cue set with [`PracticeMatch::with_cue`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Phase {
+    /// Disabled in autonomous, waiting for the match to start.
+    Countdown,
+    Autonomous,
+    /// Disabled between autonomous and teleop.
+    Delay,
+    Teleoperated,
+    /// The end of teleop, with the robot still enabled.
+    Endgame,
+    /// The match ran to the end and the robot is disabled.
+    Finished,
+    /// The match was stopped early and the robot is disabled.
+    Cancelled,
+}
+
+/// The timings of a practice match, started with [`Robot::start_practice_match`](crate::Robot::start_practice_match).
+///
+/// Every duration defaults to what the official driver station uses, and phases that last no time are skipped.
+/// While the match runs, it takes over from the robot's [`MatchTimer`] so the robot sees the time left in each period,
+/// and the robot's own timer is used again once it stops.
+pub struct PracticeMatch {
+    countdown: Duration,
+    auto: Duration,
+    delay: Duration,
+    teleop: Duration,
+    endgame: Duration,
+    cue: Option<Box<dyn FnMut(Phase) + Send>>,
+}
+
+impl Default for PracticeMatch {
+    fn default() -> Self {
+        PracticeMatch {
+            countdown: COUNTDOWN,
+            auto: AUTO,
+            delay: DELAY,
+            teleop: TELEOP,
+            endgame: ENDGAME,
+            cue: None,
+        }
+    }
+}
+
+impl PracticeMatch {
+    pub fn new() -> Self {
+        PracticeMatch::default()
+    }
+
+    /// Sets how long the robot waits, disabled, before autonomous starts.
+    pub fn with_countdown(mut self, countdown: Duration) -> Self {
+        self.countdown = countdown;
+        self
+    }
+
+    pub fn with_auto(mut self, auto: Duration) -> Self {
+        self.auto = auto;
+        self
+    }
+
+    /// Sets how long the robot is disabled between autonomous and teleop.
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    pub fn with_teleop(mut self, teleop: Duration) -> Self {
+        self.teleop = teleop;
+        self
+    }
+
+    /// Sets how long before the end of teleop the endgame starts.
+    pub fn with_endgame(mut self, endgame: Duration) -> Self {
+        self.endgame = endgame;
+        self
+    }
+
+    /// Calls `cue` as each phase starts, such as to play the field's sounds.
+    ///
+    /// The cue is called from the match's task, so it shouldn't block for long.
+    pub fn with_cue<F>(mut self, cue: F) -> Self
+    where
+        F: FnMut(Phase) + Send + 'static,
+    {
+        self.cue = Some(Box::new(cue));
+        self
+    }
+
+    /// The phases in order, with the mode and enabled state of each and how long it lasts.
+    fn phases(&self) -> [(Phase, Mode, bool, Duration); 5] {
+        let endgame = self.endgame.min(self.teleop);
+
+        [
+            (Phase::Countdown, Mode::Autonomous, false, self.countdown),
+            (Phase::Autonomous, Mode::Autonomous, true, self.auto),
+            (Phase::Delay, Mode::Teleoperated, false, self.delay),
+            (
+                Phase::Teleoperated,
+                Mode::Teleoperated,
+                true,
+                self.teleop - endgame,
+            ),
+            (Phase::Endgame, Mode::Teleoperated, true, endgame),
+        ]
+    }
+
+    fn cue(&mut self, phase: Phase) {
+        if let Some(ref mut cue) = self.cue {
+            cue(phase);
+        }
+    }
+
+    /// Steps through the match, stopping early if `cancel` is notified.
+    ///
+    /// Returns early without cueing if the UDP thread has stopped.
+    pub(crate) async fn run(mut self, udp_tx: UnboundedSender<UdpEvent>, cancel: Arc<Notify>) {
+        let send = |ev| udp_tx.send(ev).is_ok();
+
+        let timer = MatchTimer::new(self.auto, self.teleop);
+        if !send(UdpEvent::PracticeTimer(Some(timer))) {
+            return;
+        }
+
+        let mut end = Phase::Finished;
+        for (phase, mode, enabled, duration) in self.phases() {
+            if duration.is_zero() {
+                continue;
+            }
+
+            if !(send(UdpEvent::Mode(mode)) && send(UdpEvent::Enabled(enabled))) {
+                return;
+            }
+            self.cue(phase);
+
+            select! {
+                _ = tokio::time::sleep(duration) => {}
+                _ = cancel.notified() => {
+                    end = Phase::Cancelled;
+                    break;
+                }
+            }
+        }
+
+        send(UdpEvent::Enabled(false));
+        send(UdpEvent::PracticeTimer(None));
+        self.cue(end);
+    }
+}
+
+/// Makes sure only one practice match drives the robot at a time.
+#[derive(Debug, Default)]
+pub(crate) struct PracticeSlot {
+    running: StdMutex<Option<Arc<Notify>>>,
+    lock: Arc<Mutex<()>>,
+}
+
+impl PracticeSlot {
+    /// Cancels the match that is running, if any,
+    /// giving the cancel handle of the next one and a future that runs it once the slot is free.
+    pub(crate) fn start(
+        &self,
+        mut practice: PracticeMatch,
+        udp_tx: UnboundedSender<UdpEvent>,
+    ) -> (Arc<Notify>, impl Future<Output = ()>) {
+        let cancel = Arc::new(Notify::new());
+        let previous = self.running.lock().unwrap().replace(cancel.clone());
+        if let Some(previous) = previous {
+            previous.notify_one();
+        }
+
+        let lock = self.lock.clone();
+        let run = {
+            let cancel = cancel.clone();
+            async move {
+                let _running = select! {
+                    biased;
+                    _ = cancel.notified() => {
+                        practice.cue(Phase::Cancelled);
+                        return;
+                    }
+                    running = lock.lock() => running,
+                };
+
+                practice.run(udp_tx, cancel).await;
+            }
+        };
+
+        (cancel, run)
+    }
+}
+
+/// A practice match that is running, from [`Robot::start_practice_match`](crate::Robot::start_practice_match).
+///
+/// The match keeps running if this is dropped.
+#[derive(Debug)]
+pub struct PracticeRun {
+    cancel: Arc<Notify>,
+    task: JoinHandle<()>,
+}
+
+impl PracticeRun {
+    pub(crate) fn new(cancel: Arc<Notify>, task: JoinHandle<()>) -> PracticeRun {
+        PracticeRun { cancel, task }
+    }
+
+    /// Stops the match and disables the robot.
+    pub fn cancel(&self) {
+        self.cancel.notify_one();
+    }
+
+    /// Whether the match has finished or been cancelled.
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use tokio::sync::mpsc::unbounded_channel;
+
+    use super::*;
+
+    fn practice(cues: &Arc<Mutex<Vec<Phase>>>) -> PracticeMatch {
+        let cues = cues.clone();
+
+        PracticeMatch::new()
+            .with_countdown(Duration::ZERO)
+            .with_auto(Duration::from_millis(10))
+            .with_delay(Duration::from_millis(10))
+            .with_teleop(Duration::from_millis(30))
+            .with_endgame(Duration::from_millis(10))
+            .with_cue(move |phase| cues.lock().unwrap().push(phase))
+    }
+
+    #[tokio::test]
+    async fn run_practice_match() {
+        let (udp_tx, mut udp_rx) = unbounded_channel();
+        let cues = Arc::new(Mutex::new(Vec::new()));
+
+        practice(&cues).run(udp_tx, Arc::new(Notify::new())).await;
+
+        assert_eq!(
+            *cues.lock().unwrap(),
+            [
+                Phase::Autonomous,
+                Phase::Delay,
+                Phase::Teleoperated,
+                Phase::Endgame,
+                Phase::Finished,
+            ]
+        );
+
+        let mut enabled = Vec::new();
+        while let Ok(ev) = udp_rx.try_recv() {
+            if let UdpEvent::Enabled(e) = ev {
+                enabled.push(e);
+            }
+        }
+        assert_eq!(enabled, [true, false, true, true, false]);
+    }
+
+    #[tokio::test]
+    async fn cancel_practice_match() {
+        let (udp_tx, mut udp_rx) = unbounded_channel();
+        let cues = Arc::new(Mutex::new(Vec::new()));
+        let cancel = Arc::new(Notify::new());
+
+        cancel.notify_one();
+        practice(&cues).run(udp_tx, cancel).await;
+
+        assert_eq!(*cues.lock().unwrap(), [Phase::Autonomous, Phase::Cancelled]);
+
+        let mut last = None;
+        while let Ok(ev) = udp_rx.try_recv() {
+            last = Some(ev);
+        }
+        assert!(matches!(last, Some(UdpEvent::PracticeTimer(None))));
+    }
+
+    #[tokio::test]
+    async fn replace_running_practice_match() {
+        let (udp_tx, mut udp_rx) = unbounded_channel();
+        let first_cues = Arc::new(Mutex::new(Vec::new()));
+        let second_cues = Arc::new(Mutex::new(Vec::new()));
+        let slot = PracticeSlot::default();
+
+        let first = practice(&first_cues).with_auto(Duration::from_secs(5));
+        let (_, first) = slot.start(first, udp_tx.clone());
+        let first = tokio::spawn(first);
+        while first_cues.lock().unwrap().is_empty() {
+            tokio::task::yield_now().await;
+        }
+
+        let (_, second) = slot.start(practice(&second_cues), udp_tx);
+        tokio::time::timeout(Duration::from_secs(5), second)
+            .await
+            .expect("timed out");
+        first.await.unwrap();
+
+        assert_eq!(
+            *first_cues.lock().unwrap(),
+            [Phase::Autonomous, Phase::Cancelled]
+        );
+        assert_eq!(second_cues.lock().unwrap().last(), Some(&Phase::Finished));
+
+        // The second match only starts once the first has let go of the timer
+        let mut timers = Vec::new();
+        while let Ok(ev) = udp_rx.try_recv() {
+            if let UdpEvent::PracticeTimer(timer) = ev {
+                timers.push(timer.is_some());
+            }
+        }
+        assert_eq!(timers, [true, false, true, false]);
+    }
+}
@@ -0,0 +1,86 @@
+//! What the robot reports about its radio, for checking the robot is on the right network.
+
+use serde::{Deserialize, Serialize};
+
+/// The latest radio event the roboRIO sent, from [`Robot::radio`](crate::Robot::radio).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Radio {
+    event: String,
+}
+
+/// Whether the robot can be reached over the expected network, from [`Robot::radio_status`](crate::Robot::radio_status).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RadioStatus {
+    /// The robot isn't answering.
+    Unreachable,
+    /// The robot is answering, but either hasn't reported an SSID
+    /// or no SSID was given to [`RobotBuilder::with_expected_ssid`](crate::RobotBuilder::with_expected_ssid).
+    Unknown,
+    /// The robot's radio is on the expected network.
+    Connected,
+    /// The robot is answering, but its radio reports a different network,
+    /// such as when tethered while the radio is configured for another event.
+    WrongNetwork { ssid: String },
+}
+
+impl Radio {
+    pub(crate) fn new(event: String) -> Radio {
+        Radio { event }
+    }
+
+    /// The event exactly as the roboRIO sent it.
+    pub fn event(&self) -> &str {
+        &self.event
+    }
+
+    /// The SSID the radio is on, if the event mentions one.
+    ///
+    /// Events list their details as `key: value` or `key=value` pairs,
+    /// separated by commas, semicolons or new lines.
+    pub fn ssid(&self) -> Option<&str> {
+        self.event
+            .split([',', ';', '\n'])
+            .filter_map(|pair| pair.split_once([':', '=']))
+            .find(|(key, _)| key.trim().eq_ignore_ascii_case("ssid"))
+            .map(|(_, value)| value.trim())
+            .filter(|ssid| !ssid.is_empty())
+    }
+
+    pub(crate) fn status(radio: Option<&Radio>, expected: Option<&str>) -> RadioStatus {
+        let ssid = radio.and_then(|radio| radio.ssid());
+
+        match (ssid, expected) {
+            (Some(ssid), Some(expected)) if ssid == expected => RadioStatus::Connected,
+            (Some(ssid), Some(_)) => RadioStatus::WrongNetwork {
+                ssid: ssid.to_owned(),
+            },
+            _ => RadioStatus::Unknown,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_ssid() {
+        let radio = Radio::new("Radio connected, SSID: 1234_Field\n".to_owned());
+        assert_eq!(radio.ssid(), Some("1234_Field"));
+        assert_eq!(Radio::new("ssid=1234".to_owned()).ssid(), Some("1234"));
+        assert_eq!(Radio::new("Radio restarted".to_owned()).ssid(), None);
+
+        assert_eq!(
+            Radio::status(Some(&radio), Some("1234_Field")),
+            RadioStatus::Connected
+        );
+        assert_eq!(
+            Radio::status(Some(&radio), Some("1234")),
+            RadioStatus::WrongNetwork {
+                ssid: "1234_Field".to_owned()
+            }
+        );
+        assert_eq!(Radio::status(Some(&radio), None), RadioStatus::Unknown);
+        assert_eq!(Radio::status(None, Some("1234")), RadioStatus::Unknown);
+    }
+}
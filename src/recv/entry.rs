@@ -1,8 +1,3 @@
-use std::{
-    ffi::CString,
-    mem::{self, size_of},
-};
-
 use macros::ParseEntries;
 
 #[allow(non_camel_case_types)]
@@ -1,9 +1,123 @@
-use std::ffi::{c_char, CString};
+use std::{
+    ffi::{c_char, CString},
+    mem::size_of,
+};
 
 use super::entry::Entry;
+use crate::{send::tcp::truncate_for_prefix, traits::Bytes};
 
+// The size prefix that precedes every frame in the TCP stream
+const FRAME_HEADER_SIZE: usize = size_of::<u16>();
+
+#[derive(Debug, Clone)]
 pub struct TcpResponse {
-    tags: Vec<Tag>,
+    pub tags: Vec<Tag>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpParseError {
+    InvalidLength,
+    InvalidTag,
+}
+
+impl TryFrom<&[u8]> for TcpResponse {
+    type Error = TcpParseError;
+
+    /// Parses a buffer made up of complete frames.
+    ///
+    /// Use a [`Decoder`] when reading from a stream,
+    /// since a single read may end partway through a frame.
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        Ok(TcpResponse {
+            tags: Tag::parse_tags(value)?,
+        })
+    }
+}
+
+/// Splits the roboRIO's TCP stream into frames.
+///
+/// Every frame is prefixed with its size as a big-endian `u16`.
+/// Bytes are buffered until a whole frame is available,
+/// so reads can be pushed in no matter where they were cut off.
+#[derive(Debug, Clone, Default)]
+pub struct Decoder {
+    buf: Vec<u8>,
+}
+
+impl Decoder {
+    pub fn new() -> Decoder {
+        Decoder { buf: Vec::new() }
+    }
+
+    /// Appends freshly read bytes to the internal buffer.
+    pub fn extend(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// The number of buffered bytes that are not yet part of a complete frame.
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// Removes the next complete frame from the buffer, without its size prefix.
+    ///
+    /// Heartbeat frames are returned as an empty `Vec`.
+    pub fn next_frame(&mut self) -> Option<Vec<u8>> {
+        let frame = self.peek_frame()?.to_vec();
+        self.buf.drain(0..(FRAME_HEADER_SIZE + frame.len()));
+
+        Some(frame)
+    }
+
+    /// The next complete frame without its size prefix, left in the buffer.
+    pub(crate) fn peek_frame(&self) -> Option<&[u8]> {
+        let size = self.complete_frame_size()?;
+        Some(&self.buf[FRAME_HEADER_SIZE..(FRAME_HEADER_SIZE + size)])
+    }
+
+    /// Decodes every complete frame in the buffer.
+    ///
+    /// Returns `Ok(None)` if there was not a complete frame available.
+    /// Decoding stops at a frame that fails to parse, returning the tags before it.
+    /// The next call discards that frame and returns its error,
+    /// leaving the frames after it in the buffer.
+    pub fn decode(&mut self) -> Result<Option<TcpResponse>, TcpParseError> {
+        let mut tags = Vec::new();
+        let mut framed = false;
+
+        while let Some(size) = self.complete_frame_size() {
+            match Tag::parse(&self.buf[FRAME_HEADER_SIZE..(FRAME_HEADER_SIZE + size)]) {
+                Ok(tag) => tags.extend(tag),
+                Err(_) if framed => break,
+                Err(err) => {
+                    self.buf.drain(0..(FRAME_HEADER_SIZE + size));
+                    return Err(err);
+                }
+            }
+
+            framed = true;
+            self.buf.drain(0..(FRAME_HEADER_SIZE + size));
+        }
+
+        Ok(framed.then_some(TcpResponse { tags }))
+    }
+
+    /// The size of the next frame, if all of it has been buffered.
+    fn complete_frame_size(&self) -> Option<usize> {
+        let size = frame_size(&self.buf)?;
+        (self.buf.len() >= FRAME_HEADER_SIZE + size).then_some(size)
+    }
+}
+
+fn frame_size(buf: &[u8]) -> Option<usize> {
+    match buf {
+        [high, low, ..] => Some(u16::from_be_bytes([*high, *low]) as usize),
+        _ => None,
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -33,7 +147,7 @@ pub enum Tag {
         sequence: u16,
         error_code: i32,
         flags: Flags,
-        details: CString, 
+        details: CString,
         location: CString,
         call_stack: CString,
     },
@@ -41,10 +155,312 @@ pub enum Tag {
         timestamp: f32,
         sequence: u16,
         message: CString,
+    },
+}
+
+impl Tag {
+    const DISABLE_FAULTS_LENGTH: usize = 2 * 2;
+    const RAIL_FAULTS_LENGTH: usize = 3 * 2;
+
+    /// Parses a buffer made up of complete, size prefixed frames.
+    pub fn parse_tags(buf: &[u8]) -> Result<Vec<Tag>, TcpParseError> {
+        let mut tags = Vec::new();
+
+        let mut i = 0;
+        while i < buf.len() {
+            let size = frame_size(&buf[i..]).ok_or(TcpParseError::InvalidLength)?;
+            i += FRAME_HEADER_SIZE;
+
+            if size > buf.len() - i {
+                return Err(TcpParseError::InvalidLength);
+            }
+
+            if let Some(tag) = Tag::parse(&buf[i..(i + size)])? {
+                tags.push(tag);
+            }
+            i += size;
+        }
+
+        Ok(tags)
+    }
+
+    /// Parses a single frame, starting with the tag ID.
+    ///
+    /// Returns `Ok(None)` for heartbeats and tags that are not understood.
+    pub fn parse(frame: &[u8]) -> Result<Option<Tag>, TcpParseError> {
+        let (id, mut data) = match frame.split_first() {
+            Some((id, data)) => (*id, Reader::new(data)),
+            None => return Ok(None),
+        };
+
+        let tag = match id {
+            0x00 => Tag::Radio(String::from_utf8_lossy(data.rest()).into_owned()),
+            0x01 => {
+                let team_num = [data.u8()? as c_char, data.u8()? as c_char];
+                // Unknown single byte value
+                data.u8()?;
+
+                Tag::UsageReport {
+                    team_num,
+                    entries: Entry::parse_entries(cstring(data.rest())),
+                }
+            }
+            0x04 => {
+                if data.remaining() != Self::DISABLE_FAULTS_LENGTH {
+                    return Err(TcpParseError::InvalidLength);
+                }
+
+                Tag::DisableFaults {
+                    comms: data.u16()?,
+                    twelve_volt: data.u16()?,
+                }
+            }
+            0x05 => {
+                if data.remaining() != Self::RAIL_FAULTS_LENGTH {
+                    return Err(TcpParseError::InvalidLength);
+                }
+
+                Tag::RailFaults {
+                    six_volt: data.u16()?,
+                    five_volt: data.u16()?,
+                    three_three_volt: data.u16()?,
+                }
+            }
+            0x0a => {
+                let ty = Device::try_from(data.u8()?)?;
+                // Unknown two byte value
+                data.u16()?;
+                let id = data.u8()?;
+
+                let name_len = data.u8()? as usize;
+                let name = cstring(data.bytes(name_len)?);
+                let version_len = data.u8()? as usize;
+                let version = cstring(data.bytes(version_len)?);
+
+                Tag::VersionInfo {
+                    ty,
+                    id,
+                    name,
+                    version,
+                }
+            }
+            0x0b => {
+                let timestamp = data.f32()?;
+                let sequence = data.u16()?;
+                // Unknown two byte value
+                data.u16()?;
+                let error_code = data.i32()?;
+                let flags = Flags::from_bits(data.u8()?);
+
+                let details_len = data.u16()? as usize;
+                let details = cstring(data.bytes(details_len)?);
+                let location_len = data.u16()? as usize;
+                let location = cstring(data.bytes(location_len)?);
+                let call_stack_len = data.u16()? as usize;
+                let call_stack = cstring(data.bytes(call_stack_len)?);
+
+                Tag::ErrorMessage {
+                    timestamp,
+                    sequence,
+                    error_code,
+                    flags,
+                    details,
+                    location,
+                    call_stack,
+                }
+            }
+            0x0c => Tag::StandardOutput {
+                timestamp: data.f32()?,
+                sequence: data.u16()?,
+                message: cstring(data.rest()),
+            },
+            _ => return Ok(None),
+        };
+
+        Ok(Some(tag))
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+impl Tag {
+    fn id(&self) -> u8 {
+        match self {
+            Tag::Radio(_) => 0x00,
+            Tag::UsageReport { .. } => 0x01,
+            Tag::DisableFaults { .. } => 0x04,
+            Tag::RailFaults { .. } => 0x05,
+            Tag::VersionInfo { .. } => 0x0a,
+            Tag::ErrorMessage { .. } => 0x0b,
+            Tag::StandardOutput { .. } => 0x0c,
+        }
+    }
+}
+
+impl Bytes for Tag {
+    /// Writes the tag as a complete frame, prefixed with its size and ID.
+    ///
+    /// Usage reports are written without any entries,
+    /// since entries can not be turned back into their string form.
+    ///
+    /// Strings are cut off where they would overflow their size prefix or the frame's.
+    fn write_bytes(&self, out: &mut Vec<u8>) {
+        write_frame(out, self.id(), |out| {
+            let start = out.len();
+            // How many more bytes the frame's size prefix can count, which includes the ID
+            let room = |out: &Vec<u8>| usize::from(u16::MAX) - 1 - (out.len() - start);
+
+            match self {
+                Tag::Radio(event) => {
+                    let event = event.as_bytes();
+                    out.extend_from_slice(&event[..event.len().min(room(out))]);
+                }
+                Tag::UsageReport { team_num, .. } => {
+                    out.extend(team_num.iter().map(|c| *c as u8));
+                    out.push(0x00);
+                }
+                Tag::DisableFaults { comms, twelve_volt } => {
+                    out.extend_from_slice(&comms.to_be_bytes());
+                    out.extend_from_slice(&twelve_volt.to_be_bytes());
+                }
+                Tag::RailFaults {
+                    six_volt,
+                    five_volt,
+                    three_three_volt,
+                } => {
+                    out.extend_from_slice(&six_volt.to_be_bytes());
+                    out.extend_from_slice(&five_volt.to_be_bytes());
+                    out.extend_from_slice(&three_three_volt.to_be_bytes());
+                }
+                Tag::VersionInfo {
+                    ty,
+                    id,
+                    name,
+                    version,
+                } => {
+                    out.push(*ty as u8);
+                    out.extend_from_slice(&[0x00, 0x00]);
+                    out.push(*id);
+                    for s in [name, version] {
+                        let s = truncate_for_prefix(s.as_bytes());
+                        out.push(s.len() as u8);
+                        out.extend_from_slice(s);
+                    }
+                }
+                Tag::ErrorMessage {
+                    timestamp,
+                    sequence,
+                    error_code,
+                    flags,
+                    details,
+                    location,
+                    call_stack,
+                } => {
+                    out.extend_from_slice(&timestamp.to_be_bytes());
+                    out.extend_from_slice(&sequence.to_be_bytes());
+                    out.extend_from_slice(&[0x00, 0x01]);
+                    out.extend_from_slice(&error_code.to_be_bytes());
+                    out.push(flags.0);
+
+                    // The strings share what's left of the frame after their size prefixes,
+                    // which keeps each of them short enough for its own prefix too
+                    let mut room = room(out) - 3 * size_of::<u16>();
+                    for s in [details, location, call_stack] {
+                        let s = &s.as_bytes()[..s.as_bytes().len().min(room)];
+                        room -= s.len();
+                        out.extend_from_slice(&(s.len() as u16).to_be_bytes());
+                        out.extend_from_slice(s);
+                    }
+                }
+                Tag::StandardOutput {
+                    timestamp,
+                    sequence,
+                    message,
+                } => {
+                    out.extend_from_slice(&timestamp.to_be_bytes());
+                    out.extend_from_slice(&sequence.to_be_bytes());
+                    let message = message.as_bytes();
+                    out.extend_from_slice(&message[..message.len().min(room(out))]);
+                }
+            }
+        });
+    }
+}
+
+/// Writes a single frame, made up of the size as a big-endian `u16`,
+/// the tag ID, and whatever `write` adds after it.
+/// The size includes the tag ID but not itself.
+pub(crate) fn write_frame(out: &mut Vec<u8>, id: u8, write: impl FnOnce(&mut Vec<u8>)) {
+    let start = out.len();
+    out.extend_from_slice(&[0x00, 0x00, id]);
+    write(out);
+
+    let size = (out.len() - start - FRAME_HEADER_SIZE) as u16;
+    out[start..(start + FRAME_HEADER_SIZE)].copy_from_slice(&size.to_be_bytes());
+}
+
+/// Builds a [`CString`] out of the given bytes,
+/// cutting it off at the first null byte if there is one.
+pub(crate) fn cstring(bytes: &[u8]) -> CString {
+    let end = bytes
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(bytes.len());
+
+    // We can unwrap here since there are no null bytes before `end`
+    CString::new(&bytes[..end]).unwrap()
+}
+
+/// Reads big-endian values out of a single frame.
+pub(crate) struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(buf: &'a [u8]) -> Reader<'a> {
+        Reader { buf }
+    }
+
+    pub(crate) fn remaining(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub(crate) fn bytes(&mut self, len: usize) -> Result<&'a [u8], TcpParseError> {
+        if len > self.buf.len() {
+            return Err(TcpParseError::InvalidLength);
+        }
+
+        let (bytes, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], TcpParseError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+
+    pub(crate) fn rest(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.buf)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, TcpParseError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, TcpParseError> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    pub(crate) fn i32(&mut self) -> Result<i32, TcpParseError> {
+        Ok(i32::from_be_bytes(self.array()?))
+    }
+
+    pub(crate) fn f32(&mut self) -> Result<f32, TcpParseError> {
+        Ok(f32::from_be_bytes(self.array()?))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Device {
     Software = 0x00,
     CANTalon = 0x02,
@@ -52,5 +468,218 @@ pub enum Device {
     PCM = 0x09,
 }
 
+impl TryFrom<u8> for Device {
+    type Error = TcpParseError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(Device::Software),
+            0x02 => Ok(Device::CANTalon),
+            0x08 => Ok(Device::PDP),
+            0x09 => Ok(Device::PCM),
+            _ => Err(TcpParseError::InvalidTag),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Flags(u8);
+
+impl Flags {
+    const ERROR_MASK: u8 = 0x01;
+    const LV_CODE_MASK: u8 = 0x02;
+
+    pub fn from_bits(bits: u8) -> Flags {
+        Flags(bits)
+    }
+
+    /// Whether the message was reported as an error rather than a warning.
+    pub fn is_error(&self) -> bool {
+        (self.0 & Self::ERROR_MASK) > 0
+    }
+
+    /// Whether the message originated from LabVIEW code.
+    pub fn is_lv_code(&self) -> bool {
+        (self.0 & Self::LV_CODE_MASK) > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Frames taken from `netlogs/read_conn.pcapng`
+    const VERSION_INFO: &[u8] =
+        b"\x00\x2a\x0a\x00\x00\x00\x00\x0droboRIO Image\x16FRC_roboRIO2_2024_v2.2";
+    const STANDARD_OUTPUT: &[u8] = b"\x00\x0f\x0c\x3e\xad\xf0\x00\x00\x00Info AAA";
+    const HEARTBEAT: &[u8] = b"\x00\x00";
+
+    #[test]
+    fn parse_version_info() {
+        let response = TcpResponse::try_from(VERSION_INFO).unwrap();
+
+        match response.tags.as_slice() {
+            [Tag::VersionInfo {
+                ty,
+                id,
+                name,
+                version,
+            }] => {
+                assert_eq!(*ty, Device::Software);
+                assert_eq!(*id, 0);
+                assert_eq!(name.as_bytes(), b"roboRIO Image");
+                assert_eq!(version.as_bytes(), b"FRC_roboRIO2_2024_v2.2");
+            }
+            tags => panic!("unexpected tags: {tags:?}"),
+        }
+    }
+
+    #[test]
+    fn parse_error_message() {
+        let mut frame = vec![0x0b];
+        frame.extend_from_slice(&(-645.25f32).to_be_bytes());
+        frame.extend_from_slice(&[0x00, 0x05, 0x00, 0x01]);
+        frame.extend_from_slice(&44007i32.to_be_bytes());
+        frame.push(0x01);
+        for s in [b"details".as_slice(), b"location", b"\tat stack"] {
+            frame.extend_from_slice(&(s.len() as u16).to_be_bytes());
+            frame.extend_from_slice(s);
+        }
+
+        match Tag::parse(&frame) {
+            Ok(Some(Tag::ErrorMessage {
+                timestamp,
+                sequence,
+                error_code,
+                flags,
+                details,
+                location,
+                call_stack,
+            })) => {
+                assert_eq!(timestamp, -645.25);
+                assert_eq!(sequence, 5);
+                assert_eq!(error_code, 44007);
+                assert!(flags.is_error());
+                assert!(!flags.is_lv_code());
+                assert_eq!(details.as_bytes(), b"details");
+                assert_eq!(location.as_bytes(), b"location");
+                assert_eq!(call_stack.as_bytes(), b"\tat stack");
+            }
+            tag => panic!("unexpected tag: {tag:?}"),
+        }
+
+        assert_eq!(
+            Tag::parse(&frame[..frame.len() - 1]).unwrap_err(),
+            TcpParseError::InvalidLength
+        );
+    }
+
+    #[test]
+    fn decode_split_frames() {
+        let stream = [HEARTBEAT, STANDARD_OUTPUT, VERSION_INFO].concat();
+        let mut decoder = Decoder::new();
+
+        // Cut the stream partway through the standard output frame
+        decoder.extend(&stream[..7]);
+        let first = decoder.decode().unwrap().unwrap();
+        assert!(first.tags.is_empty());
+        assert_eq!(decoder.len(), 5);
+
+        decoder.extend(&stream[7..]);
+        let second = decoder.decode().unwrap().unwrap();
+        assert!(decoder.is_empty());
+        assert!(matches!(
+            second.tags.as_slice(),
+            [
+                Tag::StandardOutput { sequence: 0, message, .. },
+                Tag::VersionInfo { .. },
+            ] if message.as_bytes() == b"Info AAA"
+        ));
+
+        assert!(decoder.decode().unwrap().is_none());
+    }
+
+    #[test]
+    fn decode_past_bad_frame() {
+        // Disable faults with a single counter instead of two
+        let bad = b"\x00\x03\x04\x00\x01";
+        let stream = [STANDARD_OUTPUT, bad, VERSION_INFO].concat();
+        let mut decoder = Decoder::new();
+        decoder.extend(&stream);
+
+        let first = decoder.decode().unwrap().unwrap();
+        assert!(matches!(
+            first.tags.as_slice(),
+            [Tag::StandardOutput { sequence: 0, .. }]
+        ));
+
+        assert_eq!(decoder.decode().unwrap_err(), TcpParseError::InvalidLength);
+
+        let second = decoder.decode().unwrap().unwrap();
+        assert!(matches!(second.tags.as_slice(), [Tag::VersionInfo { .. }]));
+        assert!(decoder.is_empty());
+    }
+
+    #[test]
+    fn truncate_oversized_tags() {
+        let long = |len: usize| CString::new(vec![b'a'; len]).unwrap();
+        let tags = [
+            Tag::StandardOutput {
+                timestamp: 1.0,
+                sequence: 2,
+                message: long(70_000),
+            },
+            Tag::ErrorMessage {
+                timestamp: 1.0,
+                sequence: 3,
+                error_code: -1,
+                flags: Flags::from_bits(0x01),
+                details: long(40_000),
+                location: long(40_000),
+                call_stack: long(10),
+            },
+            Tag::VersionInfo {
+                ty: Device::Software,
+                id: 0,
+                name: long(300),
+                version: long(3),
+            },
+        ];
+
+        let mut stream = Vec::new();
+        for tag in tags.iter() {
+            tag.write_bytes(&mut stream);
+        }
+        let mut decoder = Decoder::new();
+        decoder.extend(&stream);
+
+        let response = decoder.decode().unwrap().unwrap();
+        assert!(decoder.is_empty());
+        match response.tags.as_slice() {
+            [Tag::StandardOutput {
+                sequence: 2,
+                message,
+                ..
+            }, Tag::ErrorMessage {
+                sequence: 3,
+                details,
+                location,
+                call_stack,
+                ..
+            }, Tag::VersionInfo { name, version, .. }] => {
+                // The id, timestamp, and sequence come first
+                assert_eq!(message.as_bytes().len(), usize::from(u16::MAX) - 7);
+                assert_eq!(details.as_bytes().len(), 40_000);
+                // Along with the error code, flags, and three string sizes
+                assert_eq!(
+                    location.as_bytes().len(),
+                    usize::from(u16::MAX) - 20 - 40_000
+                );
+                assert!(call_stack.as_bytes().is_empty());
+                assert_eq!(name.as_bytes().len(), 255);
+                assert_eq!(version.as_bytes(), b"aaa");
+            }
+            tags => panic!("unexpected tags: {tags:?}"),
+        }
+    }
+}
@@ -2,7 +2,7 @@ use std::mem::size_of;
 
 use serde::{Deserialize, Serialize};
 
-use crate::Mode;
+use crate::{traits::Bytes, Mode};
 
 // sequence + comm_version + status + trace + battery + first_conn + tag length
 const MIN_RESPONSE_SIZE: usize = size_of::<u16>()
@@ -12,12 +12,14 @@ const MIN_RESPONSE_SIZE: usize = size_of::<u16>()
     + size_of::<Battery>()
     + size_of::<bool>();
 
+#[derive(Debug, Clone)]
 pub struct UdpResponse {
     pub sequence: u16,
     pub comm_version: u8,
     pub status: Status,
     pub trace: Trace,
     pub battery: Battery,
+    /// Set while the robot is asking for the date and time, such as after it boots.
     pub first_conn: bool,
     pub tags: Vec<Tag>,
 }
@@ -26,6 +28,7 @@ pub struct UdpResponse {
 pub enum UdpParseError {
     InvalidLength,
     InvalidTag,
+    InvalidAlliance,
 }
 
 impl TryFrom<&[u8]> for UdpResponse {
@@ -60,19 +63,37 @@ impl TryFrom<&[u8]> for UdpResponse {
     }
 }
 
+impl Bytes for UdpResponse {
+    fn write_bytes(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.sequence.to_be_bytes());
+        out.push(self.comm_version);
+        out.push(self.status.0);
+        out.push(self.trace.0);
+        out.extend_from_slice(&self.battery.0.to_be_bytes());
+        out.push(self.first_conn as u8);
+        for tag in self.tags.iter() {
+            tag.write_bytes(out);
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Status(u8);
 
 impl Status {
-    const ESTOP_MASK: u8 = 0x80;
-    const BROWNOUT_MASK: u8 = 0x10;
-    const ENABLED_MASK: u8 = 0x04;
-    const MODE_MASK: u8 = 0x03;
+    pub(crate) const ESTOP_MASK: u8 = 0x80;
+    pub(crate) const BROWNOUT_MASK: u8 = 0x10;
+    pub(crate) const ENABLED_MASK: u8 = 0x04;
+    pub(crate) const MODE_MASK: u8 = 0x03;
 
     pub fn from_bits(bits: u8) -> Status {
         Status(bits)
     }
 
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+
     pub fn estopped(&self) -> bool {
         (self.0 & Self::ESTOP_MASK) > 0
     }
@@ -98,17 +119,21 @@ impl Status {
 pub struct Trace(u8);
 
 impl Trace {
-    const ROBOT_CODE_MASK: u8 = 0x20;
-    const IS_RIO_MASK: u8 = 0x10;
-    const TEST_MASK: u8 = 0x08;
-    const AUTO_MASK: u8 = 0x04;
-    const TELEOP_MASK: u8 = 0x02;
-    const DISABLED_MASK: u8 = 0x01;
+    pub(crate) const ROBOT_CODE_MASK: u8 = 0x20;
+    pub(crate) const IS_RIO_MASK: u8 = 0x10;
+    pub(crate) const TEST_MASK: u8 = 0x08;
+    pub(crate) const AUTO_MASK: u8 = 0x04;
+    pub(crate) const TELEOP_MASK: u8 = 0x02;
+    pub(crate) const DISABLED_MASK: u8 = 0x01;
 
     pub fn from_bits(bits: u8) -> Self {
         Trace(bits)
     }
 
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+
     pub fn robot_code(&self) -> CodeStatus {
         match self.0 & Self::ROBOT_CODE_MASK {
             0 => CodeStatus::Initializing,
@@ -133,7 +158,7 @@ impl Trace {
     }
 
     pub fn enabled(&self) -> bool {
-        !((self.0 & Self::DISABLED_MASK) > 0)
+        (self.0 & Self::DISABLED_MASK) == 0
     }
 }
 
@@ -145,6 +170,18 @@ impl Battery {
         Battery(u16::from_be_bytes(bits))
     }
 
+    /// Encodes the voltage, rounding down to the nearest 1/256th of a volt.
+    pub fn from_voltage(voltage: f32) -> Battery {
+        let xx = voltage.trunc().clamp(0.0, 255.0) as u16;
+        let yy = (voltage.fract() * 256.0).clamp(0.0, 255.0) as u16;
+
+        Battery((xx << 8) | yy)
+    }
+
+    pub fn bits(&self) -> [u8; 2] {
+        self.0.to_be_bytes()
+    }
+
     pub fn voltage(&self) -> f32 {
         let xx = (self.0 >> 8) as f32;
         let yy = (self.0 & 0xFF) as f32;
@@ -167,10 +204,8 @@ pub enum Tag {
     },
     CPUInfo {
         num_cpus: u8,
-        critical: f32,
-        above_normal: f32,
-        normal: f32,
-        low: f32,
+        /// The usage of each of the roboRIO's two CPUs.
+        usage: [CpuUsage; 2],
     },
     RAMInfo {
         block: u32,
@@ -178,7 +213,12 @@ pub enum Tag {
         free_space: u32,
     },
     PDPLog {
-        stats: [u16; 16],
+        /// The current through each channel in amps.
+        currents: [f32; 16],
+        /// The total current through the PDP in amps.
+        total_current: f32,
+        /// The PDP's temperature in degrees Celsius.
+        temperature: f32,
     },
     CANMetrics {
         utilization: f32,
@@ -189,12 +229,46 @@ pub enum Tag {
     },
 }
 
+/// The percentage of a CPU's time spent on threads of each priority.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CpuUsage {
+    pub critical: f32,
+    pub above_normal: f32,
+    pub normal: f32,
+    pub low: f32,
+}
+
+impl CpuUsage {
+    fn from_bytes(bytes: &[u8]) -> CpuUsage {
+        let float = |n: usize| {
+            f32::from_be_bytes([
+                bytes[n * 4],
+                bytes[n * 4 + 1],
+                bytes[n * 4 + 2],
+                bytes[n * 4 + 3],
+            ])
+        };
+
+        CpuUsage {
+            critical: float(0),
+            above_normal: float(1),
+            normal: float(2),
+            low: float(3),
+        }
+    }
+
+    /// The percentage of the CPU's time spent on any thread.
+    pub fn total(&self) -> f32 {
+        self.critical + self.above_normal + self.normal + self.low
+    }
+}
+
 impl Tag {
     const JOYSTICK_OUTPUT_LENGTH: u8 = 8;
     const DISK_INFO_LENGTH: u8 = 4 + 4;
     const CPU_INFO_LENGTH: u8 = 1 + 8 + 8 + 4 * 4;
     const RAM_INFO_LENGTH: u8 = 2 * 4;
-    const PDP_LOG_LENGTH: u8 = 1 + 21 + 3;
+    const PDP_LOG_LENGTH: u8 = 1 + PDP_PACKED_LENGTH as u8 + 3;
     const CAN_METRICS_LENGTH: u8 = 4 + 4 + 4 + 1 + 1;
 
     pub fn parse_tags(buf: &[u8]) -> Result<Vec<Tag>, UdpParseError> {
@@ -208,163 +282,309 @@ impl Tag {
                 return Err(UdpParseError::InvalidTag);
             }
 
-            if length > 0 {
+            // Empty tags have nothing to skip past but their size
+            if length == 0 {
                 i += 1;
-                match buf[i] {
-                    0x01 => {
-                        // Joystick Output
-                        if length - 1 != Self::JOYSTICK_OUTPUT_LENGTH {
-                            return Err(UdpParseError::InvalidTag);
-                        }
-
-                        let outputs =
-                            u32::from_be_bytes([buf[i + 1], buf[i + 2], buf[i + 3], buf[i + 4]]);
-                        let left_rumble = u16::from_be_bytes([buf[i + 5], buf[i + 6]]);
-                        let right_rumble = u16::from_be_bytes([buf[i + 7], buf[i + 8]]);
-                        i += 8;
-
-                        tags.push(Tag::JoystickOutput {
-                            outputs,
-                            left_rumble,
-                            right_rumble,
-                        });
+                continue;
+            }
+
+            i += 1;
+            match buf[i] {
+                0x01 => {
+                    // Joystick Output
+                    if length - 1 != Self::JOYSTICK_OUTPUT_LENGTH {
+                        return Err(UdpParseError::InvalidTag);
+                    }
+
+                    let outputs =
+                        u32::from_be_bytes([buf[i + 1], buf[i + 2], buf[i + 3], buf[i + 4]]);
+                    let left_rumble = u16::from_be_bytes([buf[i + 5], buf[i + 6]]);
+                    let right_rumble = u16::from_be_bytes([buf[i + 7], buf[i + 8]]);
+                    i += 8;
+
+                    tags.push(Tag::JoystickOutput {
+                        outputs,
+                        left_rumble,
+                        right_rumble,
+                    });
+                }
+                0x04 => {
+                    // Disk Info
+                    if length - 1 != Self::DISK_INFO_LENGTH {
+                        return Err(UdpParseError::InvalidTag);
                     }
-                    0x04 => {
-                        // Disk Info
-                        if length - 1 != Self::DISK_INFO_LENGTH {
-                            return Err(UdpParseError::InvalidTag);
-                        }
 
-                        // Unknown 4 byte value
-                        i += 4;
+                    // Unknown 4 byte value
+                    i += 4;
 
-                        let free_space =
-                            u32::from_be_bytes([buf[i + 1], buf[i + 2], buf[i + 3], buf[i + 4]]);
-                        i += 4;
+                    let free_space =
+                        u32::from_be_bytes([buf[i + 1], buf[i + 2], buf[i + 3], buf[i + 4]]);
+                    i += 4;
 
-                        tags.push(Tag::DiskInfo { free_space })
-                    }
-                    0x05 => {
-                        // CPU Info
-                        if length - 1 != Self::CPU_INFO_LENGTH {
-                            return Err(UdpParseError::InvalidTag);
-                        }
-
-                        let num_cpus = buf[i + 1];
-                        let critical =
-                            f32::from_be_bytes([buf[i + 2], buf[i + 3], buf[i + 4], buf[i + 5]]);
-                        let above_normal = f32::from_be_bytes([
-                            buf[i + 14],
-                            buf[i + 15],
-                            buf[i + 16],
-                            buf[i + 17],
-                        ]);
-                        let normal = f32::from_be_bytes([
-                            buf[i + 18],
-                            buf[i + 19],
-                            buf[i + 20],
-                            buf[i + 21],
-                        ]);
-                        let low = f32::from_be_bytes([
-                            buf[i + 30],
-                            buf[i + 31],
-                            buf[i + 32],
-                            buf[i + 33],
-                        ]);
-                        i += 33;
-
-                        tags.push(Tag::CPUInfo {
-                            num_cpus,
-                            critical,
-                            above_normal,
-                            normal,
-                            low,
-                        });
-                    }
-                    0x06 => {
-                        // RAM Info
-                        if length - 1 != Self::RAM_INFO_LENGTH {
-                            return Err(UdpParseError::InvalidTag);
-                        }
-
-                        let block =
-                            u32::from_be_bytes([buf[i + 1], buf[i + 2], buf[i + 3], buf[i + 4]]);
-                        let free_space =
-                            u32::from_be_bytes([buf[i + 5], buf[i + 6], buf[i + 7], buf[i + 8]]);
-                        i += 8;
-
-                        tags.push(Tag::RAMInfo { block, free_space });
-                    }
-                    0x08 => {
-                        // PDP Log
-                        if length - 1 != Self::PDP_LOG_LENGTH {
-                            return Err(UdpParseError::InvalidTag);
-                        }
-
-                        i += 1;
-                        let stats = [
-                            (buf[i] as u16) + ((buf[i + 1] as u16) << 8) * 0x03FF,
-                            (((buf[i + 1] >> 2) as u16) + ((buf[i + 2] as u16) << 6)) & 0x03FF,
-                            (((buf[i + 2] >> 4) as u16) + ((buf[i + 3] as u16) << 4)) & 0x03FF,
-                            (((buf[i + 3] >> 6) as u16) + ((buf[i + 4] as u16) << 2)) & 0x03FF,
-                            (buf[i + 5] as u16) + ((buf[i + 6] as u16) << 8) & 0x03FF,
-                            (((buf[i + 6] >> 2) as u16) + ((buf[i + 7] as u16) << 6)) & 0x03FF,
-                            (buf[i + 8] as u16) + ((buf[i + 9] as u16) << 8) * 0x03FF,
-                            (((buf[i + 9] >> 2) as u16) + ((buf[i + 10] as u16) << 6)) & 0x03FF,
-                            (((buf[i + 10] >> 4) as u16) + ((buf[i + 11] as u16) << 4)) & 0x03FF,
-                            (((buf[i + 11] >> 6) as u16) + ((buf[i + 12] as u16) << 2)) & 0x03FF,
-                            (buf[i + 13] as u16) + ((buf[i + 14] as u16) << 8) & 0x03FF,
-                            (((buf[i + 14] >> 2) as u16) + ((buf[i + 15] as u16) << 6)) & 0x03FF,
-                            (buf[i + 16] as u16) + ((buf[i + 17] as u16) << 8) * 0x03FF,
-                            (((buf[i + 17] >> 2) as u16) + ((buf[i + 18] as u16) << 6)) & 0x03FF,
-                            (((buf[i + 18] >> 4) as u16) + ((buf[i + 19] as u16) << 4)) & 0x03FF,
-                            (((buf[i + 19] >> 6) as u16) + ((buf[i + 20] as u16) << 2)) & 0x03FF,
-                        ];
-                        i += 24;
-
-                        tags.push(Tag::PDPLog { stats })
+                    tags.push(Tag::DiskInfo { free_space })
+                }
+                0x05 => {
+                    // CPU Info
+                    if length - 1 != Self::CPU_INFO_LENGTH {
+                        return Err(UdpParseError::InvalidTag);
                     }
-                    0x09 => {
-                        // Unknown value of length 9
-                        i += 9;
+
+                    // Four percentages for each CPU
+                    let num_cpus = buf[i + 1];
+                    let usage = [
+                        CpuUsage::from_bytes(&buf[(i + 2)..(i + 18)]),
+                        CpuUsage::from_bytes(&buf[(i + 18)..(i + 34)]),
+                    ];
+                    i += 33;
+
+                    tags.push(Tag::CPUInfo { num_cpus, usage });
+                }
+                0x06 => {
+                    // RAM Info
+                    if length - 1 != Self::RAM_INFO_LENGTH {
+                        return Err(UdpParseError::InvalidTag);
                     }
-                    0x0e => {
-                        // CAN Metrics
-                        if length - 1 != Self::CAN_METRICS_LENGTH {
-                            return Err(UdpParseError::InvalidLength);
-                        }
-
-                        let utilization =
-                            f32::from_be_bytes([buf[i + 1], buf[i + 2], buf[i + 3], buf[i + 4]]);
-                        let bus_off =
-                            u32::from_be_bytes([buf[i + 5], buf[i + 6], buf[i + 7], buf[i + 8]]);
-                        let tx_full =
-                            u32::from_be_bytes([buf[i + 9], buf[i + 10], buf[i + 11], buf[i + 12]]);
-                        let rx_errors = buf[i + 13];
-                        let tx_errors = buf[i + 14];
-                        i += 14;
-
-                        tags.push(Tag::CANMetrics {
-                            utilization,
-                            bus_off,
-                            tx_full,
-                            rx_errors,
-                            tx_errors,
-                        });
+
+                    let block =
+                        u32::from_be_bytes([buf[i + 1], buf[i + 2], buf[i + 3], buf[i + 4]]);
+                    let free_space =
+                        u32::from_be_bytes([buf[i + 5], buf[i + 6], buf[i + 7], buf[i + 8]]);
+                    i += 8;
+
+                    tags.push(Tag::RAMInfo { block, free_space });
+                }
+                0x08 => {
+                    // PDP Log
+                    if length - 1 != Self::PDP_LOG_LENGTH {
+                        return Err(UdpParseError::InvalidTag);
                     }
-                    _ => {}
+
+                    // Unknown single byte value, then the packed currents and the trailer
+                    let data = &buf[(i + 2)..(i + length as usize)];
+                    let currents = unpack_currents(&data[..PDP_PACKED_LENGTH]);
+                    let trailer = &data[PDP_PACKED_LENGTH..];
+                    i += Self::PDP_LOG_LENGTH as usize;
+
+                    tags.push(Tag::PDPLog {
+                        currents,
+                        total_current: u16::from_be_bytes([trailer[0], trailer[1]]) as f32
+                            * PDP_CURRENT_SCALE,
+                        temperature: trailer[2] as f32,
+                    })
                 }
+                0x0e => {
+                    // CAN Metrics
+                    if length - 1 != Self::CAN_METRICS_LENGTH {
+                        return Err(UdpParseError::InvalidLength);
+                    }
 
-                i += 1;
+                    let utilization =
+                        f32::from_be_bytes([buf[i + 1], buf[i + 2], buf[i + 3], buf[i + 4]]);
+                    let bus_off =
+                        u32::from_be_bytes([buf[i + 5], buf[i + 6], buf[i + 7], buf[i + 8]]);
+                    let tx_full =
+                        u32::from_be_bytes([buf[i + 9], buf[i + 10], buf[i + 11], buf[i + 12]]);
+                    let rx_errors = buf[i + 13];
+                    let tx_errors = buf[i + 14];
+                    i += 14;
+
+                    tags.push(Tag::CANMetrics {
+                        utilization,
+                        bus_off,
+                        tx_full,
+                        rx_errors,
+                        tx_errors,
+                    });
+                }
+                _ => {
+                    // Unknown tags are skipped, leaving `i` on their last byte
+                    i += length as usize - 1;
+                }
             }
+
+            i += 1;
         }
 
         Ok(tags)
     }
 }
 
+impl Tag {
+    fn id(&self) -> u8 {
+        match self {
+            Tag::JoystickOutput { .. } => 0x01,
+            Tag::DiskInfo { .. } => 0x04,
+            Tag::CPUInfo { .. } => 0x05,
+            Tag::RAMInfo { .. } => 0x06,
+            Tag::PDPLog { .. } => 0x08,
+            Tag::CANMetrics { .. } => 0x0e,
+        }
+    }
+}
+
+impl Bytes for Tag {
+    /// Writes the tag prefixed with its size and ID.
+    /// The size includes the ID but not itself.
+    fn write_bytes(&self, out: &mut Vec<u8>) {
+        let start = out.len();
+        out.extend_from_slice(&[0x00, self.id()]);
+
+        match self {
+            Tag::JoystickOutput {
+                outputs,
+                left_rumble,
+                right_rumble,
+            } => {
+                out.extend_from_slice(&outputs.to_be_bytes());
+                out.extend_from_slice(&left_rumble.to_be_bytes());
+                out.extend_from_slice(&right_rumble.to_be_bytes());
+            }
+            Tag::DiskInfo { free_space } => {
+                // Unknown 4 byte value
+                out.extend_from_slice(&[0x00; 4]);
+                out.extend_from_slice(&free_space.to_be_bytes());
+            }
+            Tag::CPUInfo { num_cpus, usage } => {
+                out.push(*num_cpus);
+                for cpu in usage {
+                    for percent in [cpu.critical, cpu.above_normal, cpu.normal, cpu.low] {
+                        out.extend_from_slice(&percent.to_be_bytes());
+                    }
+                }
+            }
+            Tag::RAMInfo { block, free_space } => {
+                out.extend_from_slice(&block.to_be_bytes());
+                out.extend_from_slice(&free_space.to_be_bytes());
+            }
+            Tag::PDPLog {
+                currents,
+                total_current,
+                temperature,
+            } => {
+                // Unknown single byte value
+                out.push(0x00);
+                pack_currents(currents, out);
+
+                let total = (total_current / PDP_CURRENT_SCALE).round() as u16;
+                out.extend_from_slice(&total.to_be_bytes());
+                out.push(temperature.round() as u8);
+            }
+            Tag::CANMetrics {
+                utilization,
+                bus_off,
+                tx_full,
+                rx_errors,
+                tx_errors,
+            } => {
+                out.extend_from_slice(&utilization.to_be_bytes());
+                out.extend_from_slice(&bus_off.to_be_bytes());
+                out.extend_from_slice(&tx_full.to_be_bytes());
+                out.push(*rx_errors);
+                out.push(*tx_errors);
+            }
+        }
+
+        out[start] = (out.len() - start - 1) as u8;
+    }
+}
+
+// Amps per step of a packed PDP current
+const PDP_CURRENT_SCALE: f32 = 0.125;
+// The PDP's channels are packed 10 bits at a time, least significant bit first,
+// in groups that each start on a new byte
+const PDP_GROUPS: [usize; 5] = [4, 2, 4, 2, 4];
+const PDP_PACKED_LENGTH: usize = 21;
+
+fn unpack_currents(packed: &[u8]) -> [f32; 16] {
+    let mut currents = [0.0; 16];
+    let mut channel = 0;
+    let mut start = 0;
+
+    for group in PDP_GROUPS {
+        let len = (group * 10).div_ceil(8);
+        let mut bytes = [0u8; 8];
+        bytes[..len].copy_from_slice(&packed[start..(start + len)]);
+        let bits = u64::from_le_bytes(bytes);
+
+        for n in 0..group {
+            let raw = (bits >> (n * 10)) & 0x03FF;
+            currents[channel] = raw as f32 * PDP_CURRENT_SCALE;
+            channel += 1;
+        }
+        start += len;
+    }
+
+    currents
+}
+
+fn pack_currents(currents: &[f32; 16], out: &mut Vec<u8>) {
+    let mut channels = currents.iter();
+
+    for group in PDP_GROUPS {
+        let mut bits = 0u64;
+        for (n, current) in channels.by_ref().take(group).enumerate() {
+            let raw = (current / PDP_CURRENT_SCALE).round().clamp(0.0, 1023.0) as u64;
+            bits |= raw << (n * 10);
+        }
+
+        let len = (group * 10).div_ceil(8);
+        out.extend_from_slice(&bits.to_le_bytes()[..len]);
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum CodeStatus {
     Running,
     Initializing,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Channel n carries n + 1 amps, packed by hand
+    const PDP_LOG: &[u8] = &[
+        0x1a, 0x08, // Size and ID
+        0x00, // Unknown
+        0x08, 0x40, 0x80, 0x01, 0x08, // Channels 0-3
+        0x28, 0xc0, 0x00, // Channels 4-5
+        0x38, 0x00, 0x81, 0x04, 0x14, // Channels 6-9
+        0x58, 0x80, 0x01, // Channels 10-11
+        0x68, 0xc0, 0x81, 0x07, 0x20, // Channels 12-15
+        0x04, 0x40, 0x2a, // Total current and temperature
+    ];
+
+    #[test]
+    fn parse_pdp_log() {
+        let tags = Tag::parse_tags(PDP_LOG).unwrap();
+
+        let expected: [f32; 16] = std::array::from_fn(|n| n as f32 + 1.0);
+        assert_eq!(
+            tags,
+            [Tag::PDPLog {
+                currents: expected,
+                total_current: 136.0,
+                temperature: 42.0,
+            }]
+        );
+
+        let mut out = Vec::new();
+        tags[0].write_bytes(&mut out);
+        assert_eq!(out, PDP_LOG);
+    }
+
+    #[test]
+    fn parse_pdp_log_extremes() {
+        let mut log = PDP_LOG.to_vec();
+        // Every bit of channels 0-3 set
+        log[3..8].copy_from_slice(&[0xff; 5]);
+
+        let tags = Tag::parse_tags(&log).unwrap();
+        let Tag::PDPLog { currents, .. } = tags[0] else {
+            panic!("expected a PDP log, got {tags:?}");
+        };
+
+        assert_eq!(currents[..4], [127.875; 4]);
+        assert_eq!(currents[4], 5.0);
+    }
+}
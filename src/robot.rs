@@ -0,0 +1,528 @@
+//! The roboRIO's side of the protocol, for simulating a robot or testing other driver stations.
+//!
+//! [`Roborio`] binds the robot's UDP and TCP ports, answers every control packet with a configurable [`Reply`],
+//! and keeps track of what the driver station is asking the robot to do in a [`ControlState`].
+
+use std::{
+    ffi::CString,
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream, UdpSocket},
+    select,
+    sync::{broadcast, RwLock},
+    task::{JoinHandle, JoinSet},
+};
+
+use crate::{
+    recv::{
+        tcp::{self, Decoder},
+        udp::{self as recv_udp, Battery, Status, Trace, UdpResponse},
+    },
+    send::{
+        tcp::{self as send_tcp, MatchInfo},
+        udp::{self as send_udp, Buttons, Control, Packet, Request},
+    },
+    traits::Bytes,
+    Alliance, GameData, Mode,
+};
+
+pub(crate) const UDP_PORT: u16 = 1110;
+pub(crate) const TCP_PORT: u16 = 1740;
+pub(crate) const DS_UDP_RX_PORT: u16 = 1150;
+
+// How long the driver station can go without sending a control packet before the robot disables
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How the robot answers each control packet.
+#[derive(Debug, Clone)]
+pub struct Reply {
+    /// The status to report, or `None` to mirror the control bits the driver station sent.
+    pub status: Option<Status>,
+    /// The trace to report, or `None` to report running code in the mode the driver station sent.
+    pub trace: Option<Trace>,
+    pub battery: Battery,
+    pub first_conn: bool,
+    /// Tags sent with every reply.
+    pub tags: Vec<recv_udp::Tag>,
+}
+
+impl Reply {
+    pub fn with_status(mut self, status: Status) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    pub fn with_trace(mut self, trace: Trace) -> Self {
+        self.trace = Some(trace);
+        self
+    }
+
+    pub fn with_battery(mut self, voltage: f32) -> Self {
+        self.battery = Battery::from_voltage(voltage);
+        self
+    }
+
+    pub fn with_first_conn(mut self, first_conn: bool) -> Self {
+        self.first_conn = first_conn;
+        self
+    }
+
+    pub fn with_tag(mut self, tag: recv_udp::Tag) -> Self {
+        self.tags.push(tag);
+        self
+    }
+
+    pub(crate) fn response(&self, packet: &Packet) -> UdpResponse {
+        let control = packet.control();
+
+        UdpResponse {
+            sequence: packet.sequence(),
+            comm_version: packet.version(),
+            status: self.status.unwrap_or_else(|| mirror_status(control)),
+            trace: self.trace.unwrap_or_else(|| mirror_trace(control)),
+            battery: self.battery,
+            first_conn: self.first_conn,
+            tags: self.tags.clone(),
+        }
+    }
+}
+
+impl Default for Reply {
+    fn default() -> Self {
+        Reply {
+            status: None,
+            trace: None,
+            battery: Battery::from_voltage(12.5),
+            first_conn: false,
+            tags: Vec::new(),
+        }
+    }
+}
+
+fn mirror_status(control: Control) -> Status {
+    // The control and status bytes share a layout, apart from the FMS bit
+    Status::from_bits(
+        control.bits() & (Status::ESTOP_MASK | Status::ENABLED_MASK | Status::MODE_MASK),
+    )
+}
+
+fn mirror_trace(control: Control) -> Trace {
+    let mode = if !control.enabled() {
+        Trace::DISABLED_MASK
+    } else {
+        match control.mode() {
+            Mode::Teleoperated => Trace::TELEOP_MASK,
+            Mode::Autonomous => Trace::AUTO_MASK,
+            Mode::Test => Trace::TEST_MASK,
+        }
+    };
+
+    Trace::from_bits(Trace::ROBOT_CODE_MASK | Trace::IS_RIO_MASK | mode)
+}
+
+/// The values of a joystick from the latest control packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JoystickValues {
+    pub axes: Vec<i8>,
+    pub buttons: Buttons,
+    pub povs: Vec<i16>,
+}
+
+/// What the driver station has asked the robot to do, from [`Roborio::control`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ControlState {
+    /// When the latest control packet arrived, or `None` if none have.
+    pub last_packet: Option<Instant>,
+    pub sequence: u16,
+    pub control: Control,
+    pub request: Request,
+    pub alliance: Alliance,
+    /// The time left in the match period, if the latest control packet had it.
+    pub countdown: Option<f32>,
+    /// The values of the joystick in each slot, from the latest control packet.
+    pub joysticks: Vec<JoystickValues>,
+    /// The latest [`Date`](send_udp::Tag::Date) tag the driver station sent.
+    pub date: Option<send_udp::Tag>,
+    pub timezone: Option<CString>,
+    pub game_data: Option<GameData>,
+    pub match_info: Option<MatchInfo>,
+    /// The latest descriptor of each joystick, sorted by slot.
+    pub descriptors: Vec<send_tcp::Joystick>,
+}
+
+impl Default for ControlState {
+    fn default() -> Self {
+        ControlState {
+            last_packet: None,
+            sequence: 0,
+            control: Control::default(),
+            request: Request::default(),
+            alliance: Alliance::Red1,
+            countdown: None,
+            joysticks: Vec::new(),
+            date: None,
+            timezone: None,
+            game_data: None,
+            match_info: None,
+            descriptors: Vec::new(),
+        }
+    }
+}
+
+impl ControlState {
+    /// Whether a control packet has arrived recently enough for the robot to stay enabled.
+    pub fn ds_connected(&self) -> bool {
+        self.last_packet
+            .is_some_and(|last| last.elapsed() < TIMEOUT)
+    }
+
+    /// Whether the robot should be running its enabled code,
+    /// which stops as soon as the driver station is lost.
+    pub fn enabled(&self) -> bool {
+        self.control.enabled() && !self.control.estopped() && self.ds_connected()
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.control.mode()
+    }
+
+    fn apply_udp(&mut self, packet: &Packet, received: Instant) {
+        self.last_packet = Some(received);
+        self.sequence = packet.sequence();
+        self.control = packet.control();
+        self.request = packet.request();
+        self.alliance = packet.alliance();
+        self.countdown = None;
+        self.joysticks.clear();
+
+        for tag in packet.tags() {
+            match tag {
+                send_udp::Tag::Countdown(countdown) => self.countdown = Some(*countdown),
+                send_udp::Tag::Joystick {
+                    axes,
+                    buttons,
+                    povs,
+                } => self.joysticks.push(JoystickValues {
+                    axes: axes.clone(),
+                    buttons: buttons.clone(),
+                    povs: povs.clone(),
+                }),
+                send_udp::Tag::Date { .. } => self.date = Some(tag.clone()),
+                send_udp::Tag::Timezone(timezone) => self.timezone = Some(timezone.clone()),
+            }
+        }
+    }
+
+    fn apply_tcp(&mut self, packet: send_tcp::Packet) {
+        if let Some(game_data) = packet.game_data() {
+            self.game_data = Some(game_data);
+        }
+        if let Some(match_info) = packet.match_info() {
+            self.match_info = Some(match_info.clone());
+        }
+
+        for joystick in packet.joysticks() {
+            self.descriptors
+                .retain(|descriptor| descriptor.index() != joystick.index());
+            self.descriptors.push(joystick.clone());
+        }
+        self.descriptors
+            .sort_by_key(|descriptor| descriptor.index());
+    }
+}
+
+/// Sees everything a [`Roborio`] receives from the driver station, before it's answered.
+pub(crate) trait Tap: Send + Sync {
+    fn udp(&self, packet: &Packet);
+    fn tcp_connection(&self);
+    /// Called with every TCP frame, without its size prefix.
+    fn tcp_frame(&self, frame: &[u8]);
+}
+
+/// A simulated roboRIO that a driver station can connect to.
+///
+/// Like a real roboRIO, it keeps asking for the date in its replies until the driver station sends it.
+#[derive(Debug)]
+pub struct Roborio {
+    reply: Arc<RwLock<Reply>>,
+    control: Arc<RwLock<ControlState>>,
+    tcp_out: broadcast::Sender<Vec<u8>>,
+    udp_addr: SocketAddr,
+    tcp_addr: SocketAddr,
+    tasks: [JoinHandle<()>; 2],
+}
+
+impl Roborio {
+    /// Binds the roboRIO's usual ports on every interface.
+    pub async fn bind() -> io::Result<Roborio> {
+        Roborio::bind_to(
+            Ipv4Addr::UNSPECIFIED.into(),
+            UDP_PORT,
+            TCP_PORT,
+            DS_UDP_RX_PORT,
+        )
+        .await
+    }
+
+    /// Binds the given ports on `ip`, replying to the driver station on `ds_port`.
+    ///
+    /// Passing `0` for `udp_port` or `tcp_port` picks a free port,
+    /// which can be found with [`Roborio::udp_addr`] and [`Roborio::tcp_addr`].
+    pub async fn bind_to(
+        ip: IpAddr,
+        udp_port: u16,
+        tcp_port: u16,
+        ds_port: u16,
+    ) -> io::Result<Roborio> {
+        Roborio::bind_tapped(ip, udp_port, tcp_port, ds_port, None).await
+    }
+
+    /// Binds like [`Roborio::bind_to`], showing everything received to `tap`.
+    pub(crate) async fn bind_tapped(
+        ip: IpAddr,
+        udp_port: u16,
+        tcp_port: u16,
+        ds_port: u16,
+        tap: Option<Arc<dyn Tap>>,
+    ) -> io::Result<Roborio> {
+        let udp = UdpSocket::bind(SocketAddr::new(ip, udp_port)).await?;
+        let tcp = TcpListener::bind(SocketAddr::new(ip, tcp_port)).await?;
+        let udp_addr = udp.local_addr()?;
+        let tcp_addr = tcp.local_addr()?;
+
+        let reply = Arc::new(RwLock::new(Reply::default()));
+        let control = Arc::new(RwLock::new(ControlState::default()));
+        let (tcp_out, _) = broadcast::channel(64);
+
+        let tasks = [
+            tokio::spawn(udp_task(
+                udp,
+                ds_port,
+                reply.clone(),
+                control.clone(),
+                tap.clone(),
+            )),
+            tokio::spawn(tcp_task(tcp, tcp_out.clone(), control.clone(), tap)),
+        ];
+
+        Ok(Roborio {
+            reply,
+            control,
+            tcp_out,
+            udp_addr,
+            tcp_addr,
+            tasks,
+        })
+    }
+
+    pub fn udp_addr(&self) -> SocketAddr {
+        self.udp_addr
+    }
+
+    pub fn tcp_addr(&self) -> SocketAddr {
+        self.tcp_addr
+    }
+
+    /// Changes how every following control packet is answered.
+    pub async fn set_reply(&self, reply: Reply) {
+        *self.reply.write().await = reply;
+    }
+
+    pub async fn control(&self) -> ControlState {
+        self.control.read().await.clone()
+    }
+
+    /// Sends a tag to every connected driver station over TCP, such as console output.
+    ///
+    /// Returns `false` if no driver station was connected to receive it.
+    pub fn send_tcp(&self, tag: &tcp::Tag) -> bool {
+        let mut frame = Vec::new();
+        tag.write_bytes(&mut frame);
+
+        self.tcp_out.send(frame).is_ok()
+    }
+}
+
+impl Drop for Roborio {
+    fn drop(&mut self) {
+        for task in self.tasks.iter() {
+            task.abort();
+        }
+    }
+}
+
+async fn udp_task(
+    socket: UdpSocket,
+    ds_port: u16,
+    reply: Arc<RwLock<Reply>>,
+    control: Arc<RwLock<ControlState>>,
+    tap: Option<Arc<dyn Tap>>,
+) {
+    let mut buf = [0u8; 1024];
+
+    loop {
+        let (bytes, mut addr) = match socket.recv_from(&mut buf).await {
+            Ok(recv) => recv,
+            Err(_) => continue,
+        };
+
+        let packet = match Packet::try_from(&buf[0..bytes]) {
+            Ok(packet) => packet,
+            Err(_) => continue,
+        };
+        if let Some(tap) = &tap {
+            tap.udp(&packet);
+        }
+
+        let mut current = control.write().await;
+        current.apply_udp(&packet, Instant::now());
+
+        let mut response = reply.read().await.response(&packet);
+        response.first_conn |= current.date.is_none();
+        drop(current);
+
+        let mut send = Vec::new();
+        response.write_bytes(&mut send);
+
+        addr.set_port(ds_port);
+        let _ = socket.send_to(&send, addr).await;
+    }
+}
+
+async fn tcp_task(
+    listener: TcpListener,
+    tcp_out: broadcast::Sender<Vec<u8>>,
+    control: Arc<RwLock<ControlState>>,
+    tap: Option<Arc<dyn Tap>>,
+) {
+    // Connections are aborted along with this task when the set is dropped
+    let mut connections = JoinSet::new();
+
+    loop {
+        let Ok((conn, _)) = listener.accept().await else {
+            continue;
+        };
+        // Forget the connections that have closed since the last one was accepted
+        while connections.try_join_next().is_some() {}
+
+        if let Some(tap) = &tap {
+            tap.tcp_connection();
+        }
+        connections.spawn(tcp_connection(
+            conn,
+            tcp_out.subscribe(),
+            control.clone(),
+            tap.clone(),
+        ));
+    }
+}
+
+async fn tcp_connection(
+    conn: TcpStream,
+    mut tcp_out: broadcast::Receiver<Vec<u8>>,
+    control: Arc<RwLock<ControlState>>,
+    tap: Option<Arc<dyn Tap>>,
+) {
+    let (mut reader, mut writer) = conn.into_split();
+    let mut decoder = Decoder::new();
+    let mut buf = [0u8; 1024];
+
+    loop {
+        select! {
+            read = reader.read(&mut buf) => match read {
+                Ok(0) | Err(_) => return,
+                Ok(bytes) => {
+                    decoder.extend(&buf[0..bytes]);
+                    while let Some(frame) = decoder.next_frame() {
+                        if let Some(tap) = &tap {
+                            tap.tcp_frame(&frame);
+                        }
+                        // Frames that fail to parse are skipped
+                        if let Ok(packet) = send_tcp::Packet::from_frame(&frame) {
+                            control.write().await.apply_tcp(packet);
+                        }
+                    }
+                }
+            },
+            frame = tcp_out.recv() => match frame {
+                Ok(frame) => {
+                    if writer.write_all(&frame).await.is_err() {
+                        return;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{until, within};
+
+    #[tokio::test]
+    async fn track_control_state() {
+        let ds = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let ds_port = ds.local_addr().unwrap().port();
+        let roborio = Roborio::bind_to(Ipv4Addr::LOCALHOST.into(), 0, 0, ds_port)
+            .await
+            .unwrap();
+
+        let mut buttons = Buttons::new(2);
+        buttons.set_button(1, true);
+
+        let mut send = Vec::new();
+        Packet::default()
+            .with_sequence(7)
+            .with_enabled(true)
+            .with_mode(Mode::Autonomous)
+            .with_alliance(Alliance::Blue3)
+            .with_tag(send_udp::Tag::Countdown(14.0))
+            .with_tag(send_udp::Tag::Joystick {
+                axes: vec![64],
+                buttons,
+                povs: vec![-1],
+            })
+            .write_bytes(&mut send);
+        ds.send_to(&send, roborio.udp_addr()).await.unwrap();
+
+        let mut buf = [0u8; 1024];
+        let bytes = within(ds.recv(&mut buf)).await.unwrap();
+        let response = UdpResponse::try_from(&buf[0..bytes]).unwrap();
+        assert_eq!(response.sequence, 7);
+        assert!(response.status.enabled());
+        assert_eq!(response.status.mode(), Mode::Autonomous);
+        // The date hasn't been sent yet
+        assert!(response.first_conn);
+
+        let control = roborio.control().await;
+        assert!(control.enabled());
+        assert_eq!(control.alliance, Alliance::Blue3);
+        assert_eq!(control.countdown, Some(14.0));
+        assert_eq!(control.joysticks[0].axes, [64]);
+        assert!(control.joysticks[0].buttons.button(1));
+
+        let mut conn = within(TcpStream::connect(roborio.tcp_addr()))
+            .await
+            .unwrap();
+        let mut send = Vec::new();
+        send_tcp::Packet::default()
+            .with_game_data(Some(GameData::double(b'R', b'B')))
+            .write_bytes(&mut send);
+        conn.write_all(&send).await.unwrap();
+
+        until(|| async { roborio.control().await.game_data.is_some() }).await;
+        assert_eq!(
+            roborio.control().await.game_data,
+            Some(GameData::double(b'R', b'B'))
+        );
+    }
+}
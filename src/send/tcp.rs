@@ -1,7 +1,13 @@
 use std::ffi::CString;
 
-use crate::{traits::Bytes, GameData};
+use crate::{
+    recv::tcp::{cstring, write_frame, Decoder, Reader, TcpParseError},
+    send::udp::Buttons,
+    traits::Bytes,
+    GameData,
+};
 
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct Packet {
     game_data: Option<GameData>,
     match_info: Option<MatchInfo>,
@@ -33,6 +39,109 @@ impl Packet {
         self.joysticks.append(&mut joysticks);
         self
     }
+
+    pub fn game_data(&self) -> Option<GameData> {
+        self.game_data
+    }
+
+    pub fn match_info(&self) -> Option<&MatchInfo> {
+        self.match_info.as_ref()
+    }
+
+    pub fn joysticks(&self) -> &[Joystick] {
+        &self.joysticks
+    }
+}
+
+impl Packet {
+    const JOYSTICK_TAG: u8 = 0x02;
+    const MATCH_INFO_TAG: u8 = 0x07;
+    const GAME_DATA_TAG: u8 = 0x0e;
+
+    /// Parses a single frame, starting with the tag ID, into a packet holding just that tag.
+    ///
+    /// Heartbeats and tags that are not understood give an empty packet.
+    pub fn from_frame(frame: &[u8]) -> Result<Packet, TcpParseError> {
+        let mut packet = Packet::default();
+        packet.apply_frame(frame)?;
+        Ok(packet)
+    }
+
+    /// Decodes every complete frame buffered in the decoder into one packet.
+    ///
+    /// Returns `Ok(None)` if there was not a complete frame available.
+    /// Decoding stops at a frame that fails to parse, returning the packet built before it.
+    /// The next call discards that frame and returns its error,
+    /// leaving the frames after it in the decoder.
+    pub fn decode(decoder: &mut Decoder) -> Result<Option<Packet>, TcpParseError> {
+        let mut packet = Packet::default();
+        let mut framed = false;
+
+        while let Some(frame) = decoder.peek_frame() {
+            match packet.apply_frame(frame) {
+                Ok(()) => {}
+                Err(_) if framed => break,
+                Err(err) => {
+                    decoder.next_frame();
+                    return Err(err);
+                }
+            }
+
+            framed = true;
+            decoder.next_frame();
+        }
+
+        Ok(framed.then_some(packet))
+    }
+
+    /// Adds the tag in `frame` to the packet, leaving it untouched if the frame fails to parse.
+    fn apply_frame(&mut self, frame: &[u8]) -> Result<(), TcpParseError> {
+        let (id, mut data) = match frame.split_first() {
+            Some((id, data)) => (*id, Reader::new(data)),
+            None => return Ok(()),
+        };
+
+        let finished = |data: &Reader| match data.remaining() {
+            0 => Ok(()),
+            _ => Err(TcpParseError::InvalidLength),
+        };
+
+        match id {
+            Self::JOYSTICK_TAG => {
+                let joystick = Joystick::read(&mut data)?;
+                finished(&data)?;
+                self.joysticks.push(joystick);
+            }
+            Self::MATCH_INFO_TAG => {
+                let match_info = MatchInfo::read(&mut data)?;
+                finished(&data)?;
+                self.match_info = Some(match_info);
+            }
+            Self::GAME_DATA_TAG => {
+                let game_data = GameData::from_bytes(data.rest());
+                self.game_data = Some(game_data.ok_or(TcpParseError::InvalidLength)?);
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+impl TryFrom<&[u8]> for Packet {
+    type Error = TcpParseError;
+
+    /// Parses a buffer made up of complete, size prefixed frames.
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let mut decoder = Decoder::new();
+        decoder.extend(value);
+
+        let packet = Packet::decode(&mut decoder)?.unwrap_or_default();
+        match decoder.is_empty() {
+            true => Ok(packet),
+            false => Err(TcpParseError::InvalidLength),
+        }
+    }
 }
 
 impl Bytes for Packet {
@@ -42,36 +151,33 @@ impl Bytes for Packet {
             return;
         }
 
-        if let Some(game_data) = self.game_data {
-            out.push(game_data.len() + 1);
-            out.push(0x0e);
-            game_data.write_bytes(out);
+        for joystick in self.joysticks.iter() {
+            write_tag(out, Self::JOYSTICK_TAG, joystick);
         }
 
         if let Some(ref match_info) = self.match_info {
-            out.push(match_info.len());
-            out.push(0x07);
-            match_info.write_bytes(out);
+            write_tag(out, Self::MATCH_INFO_TAG, match_info);
         }
-    }
-}
 
-impl Default for Packet {
-    fn default() -> Self {
-        Packet {
-            game_data: None,
-            match_info: None,
-            joysticks: Vec::new(),
+        if let Some(ref game_data) = self.game_data {
+            write_tag(out, Self::GAME_DATA_TAG, game_data);
         }
     }
 }
 
+/// Writes the tag's contents as a single frame with the given ID.
+fn write_tag<T: Bytes>(out: &mut Vec<u8>, id: u8, tag: &T) {
+    write_frame(out, id, |out| tag.write_bytes(out));
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum TcpEvent {
     GameData(GameData),
     MatchInfo(MatchInfo),
     Joystick(Joystick),
     TeamNumber,
+    /// Reconnects, so the roboRIO reports its versions again.
+    RequestVersions,
     Exit,
 }
 
@@ -82,26 +188,38 @@ pub struct MatchInfo {
 }
 
 impl MatchInfo {
+    // Written in place of the competition when there isn't one
+    const NO_COMPETITION: [u8; 5] = [0x00; 5];
+
     pub fn new(competition: Option<CString>, ty: MatchType) -> Self {
         MatchInfo { competition, ty }
     }
 
-    fn len(&self) -> u8 {
-        let competition_len = match self.competition {
-            Some(ref competition) => competition.as_bytes().len(),
-            None => 5,
+    pub fn competition(&self) -> Option<&CString> {
+        self.competition.as_ref()
+    }
+
+    pub fn ty(&self) -> MatchType {
+        self.ty
+    }
+
+    fn read(data: &mut Reader) -> Result<MatchInfo, TcpParseError> {
+        let competition_len = data.u8()? as usize;
+        let competition = match data.bytes(competition_len)? {
+            bytes if bytes == Self::NO_COMPETITION => None,
+            bytes => Some(cstring(bytes)),
         };
+        let ty = MatchType::try_from(data.u8()?)?;
 
-        // The length of the internal string, plus two bytes for the match type and string length
-        (competition_len as u8) + 2
+        Ok(MatchInfo { competition, ty })
     }
 }
 
 impl Bytes for MatchInfo {
     fn write_bytes(&self, out: &mut Vec<u8>) {
         let competition = match self.competition {
-            Some(ref competition) => competition.as_bytes(),
-            None => [0x00, 0x00, 0x00, 0x00, 0x00].as_slice(),
+            Some(ref competition) => truncate_for_prefix(competition.as_bytes()),
+            None => Self::NO_COMPETITION.as_slice(),
         };
         out.push(competition.len() as u8);
         out.extend_from_slice(competition);
@@ -118,6 +236,20 @@ pub enum MatchType {
     Eliminations,
 }
 
+impl TryFrom<u8> for MatchType {
+    type Error = TcpParseError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(MatchType::None),
+            1 => Ok(MatchType::Practice),
+            2 => Ok(MatchType::Qualifications),
+            3 => Ok(MatchType::Eliminations),
+            _ => Err(TcpParseError::InvalidTag),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Joystick {
     index: u8,
@@ -129,24 +261,145 @@ pub struct Joystick {
     pov_count: u8,
 }
 
+impl Joystick {
+    /// The most axes the robot's HAL keeps for a joystick.
+    pub const MAX_AXES: u8 = 12;
+    /// The most POVs the robot's HAL keeps for a joystick.
+    pub const MAX_POVS: u8 = 12;
+
+    /// Describes the joystick plugged into the slot at `index`.
+    ///
+    /// The joystick starts out with no axes, buttons, or POVs.
+    pub fn new(index: u8, ty: JoystickType, name: CString) -> Self {
+        Joystick {
+            index,
+            is_xbox: false,
+            ty,
+            name,
+            axis_types: Vec::new(),
+            button_count: 0,
+            pov_count: 0,
+        }
+    }
+
+    pub fn with_index(mut self, index: u8) -> Self {
+        self.index = index;
+        self
+    }
+
+    pub fn with_xbox(mut self, is_xbox: bool) -> Self {
+        self.is_xbox = is_xbox;
+        self
+    }
+
+    /// Adds an axis, unless there are already [`Joystick::MAX_AXES`].
+    pub fn with_axis(mut self, axis: AxisType) -> Self {
+        if self.axis_types.len() < Self::MAX_AXES as usize {
+            self.axis_types.push(axis);
+        }
+        self
+    }
+
+    /// Sets the axes, dropping any past [`Joystick::MAX_AXES`].
+    pub fn with_axes(mut self, mut axis_types: Vec<AxisType>) -> Self {
+        axis_types.truncate(Self::MAX_AXES as usize);
+        self.axis_types = axis_types;
+        self
+    }
+
+    /// Sets the number of buttons, up to [`Buttons::MAX_COUNT`].
+    pub fn with_button_count(mut self, button_count: u8) -> Self {
+        self.button_count = button_count.min(Buttons::MAX_COUNT);
+        self
+    }
+
+    /// Sets the number of POVs, up to [`Joystick::MAX_POVS`].
+    pub fn with_pov_count(mut self, pov_count: u8) -> Self {
+        self.pov_count = pov_count.min(Self::MAX_POVS);
+        self
+    }
+
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+
+    pub fn is_xbox(&self) -> bool {
+        self.is_xbox
+    }
+
+    pub fn ty(&self) -> JoystickType {
+        self.ty
+    }
+
+    pub fn name(&self) -> &CString {
+        &self.name
+    }
+
+    pub fn axis_types(&self) -> &[AxisType] {
+        &self.axis_types
+    }
+
+    pub fn button_count(&self) -> u8 {
+        self.button_count
+    }
+
+    pub fn pov_count(&self) -> u8 {
+        self.pov_count
+    }
+
+    fn read(data: &mut Reader) -> Result<Joystick, TcpParseError> {
+        let index = data.u8()?;
+        let is_xbox = data.u8()? > 0;
+        let ty = JoystickType::try_from(data.u8()? as i8)?;
+
+        let name_len = data.u8()? as usize;
+        let name = cstring(data.bytes(name_len)?);
+
+        let axis_count = data.u8()? as usize;
+        let axis_types = data
+            .bytes(axis_count)?
+            .iter()
+            .map(|ty| AxisType::try_from(*ty))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Joystick {
+            index,
+            is_xbox,
+            ty,
+            name,
+            axis_types,
+            button_count: data.u8()?,
+            pov_count: data.u8()?,
+        })
+    }
+}
+
 impl Bytes for Joystick {
     fn write_bytes(&self, out: &mut Vec<u8>) {
         out.push(self.index);
         out.push(self.is_xbox as u8);
 
-        out.push(unsafe { std::mem::transmute(self.ty as i8) });
-        // SAFETY: will be reinterpreted as an `i8` when recieved
-        out.extend_from_slice(self.name.as_bytes());
+        // Will be reinterpreted as an `i8` when recieved
+        out.push(self.ty as i8 as u8);
+
+        let name = truncate_for_prefix(self.name.as_bytes());
+        out.push(name.len() as u8);
+        out.extend_from_slice(name);
 
-        out.push(self.axis_types.len() as u8);
-        // SAFETY: `AxisType` is stored as `repr(u8)`, and will be interpreted as such
-        out.extend_from_slice(unsafe { std::mem::transmute(self.axis_types.as_slice()) });
+        let axis_types = &self.axis_types[..self.axis_types.len().min(u8::MAX as usize)];
+        out.push(axis_types.len() as u8);
+        out.extend(axis_types.iter().map(|ty| *ty as u8));
 
         out.push(self.button_count);
         out.push(self.pov_count);
     }
 }
 
+/// Cuts `bytes` off at 255, the most a `u8` length prefix can count.
+pub(crate) fn truncate_for_prefix(bytes: &[u8]) -> &[u8] {
+    &bytes[..bytes.len().min(u8::MAX as usize)]
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(i8)]
 pub enum JoystickType {
@@ -169,6 +422,33 @@ pub enum JoystickType {
     HIDFirstPerson,
 }
 
+impl TryFrom<i8> for JoystickType {
+    type Error = TcpParseError;
+
+    fn try_from(value: i8) -> Result<Self, Self::Error> {
+        match value {
+            -1 => Ok(JoystickType::Unknown),
+            0 => Ok(JoystickType::XInputUnknown),
+            1 => Ok(JoystickType::XInputGamepad),
+            2 => Ok(JoystickType::XInputWheel),
+            3 => Ok(JoystickType::XInputArcade),
+            4 => Ok(JoystickType::XInputFlightStick),
+            5 => Ok(JoystickType::XInputDancePad),
+            6 => Ok(JoystickType::XInputGuitar),
+            7 => Ok(JoystickType::XInputGuitar2),
+            8 => Ok(JoystickType::XInputDrumKit),
+            11 => Ok(JoystickType::XInputGuitar3),
+            19 => Ok(JoystickType::XInputArcadePad),
+            20 => Ok(JoystickType::HIDJoystick),
+            21 => Ok(JoystickType::HIDGamepad),
+            22 => Ok(JoystickType::HIDDriving),
+            23 => Ok(JoystickType::HIDFlight),
+            24 => Ok(JoystickType::HIDFirstPerson),
+            _ => Err(TcpParseError::InvalidTag),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(u8)]
 pub enum AxisType {
@@ -178,3 +458,188 @@ pub enum AxisType {
     Twist,
     Throttle,
 }
+
+impl TryFrom<u8> for AxisType {
+    type Error = TcpParseError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(AxisType::X),
+            1 => Ok(AxisType::Y),
+            2 => Ok(AxisType::Z),
+            3 => Ok(AxisType::Twist),
+            4 => Ok(AxisType::Throttle),
+            _ => Err(TcpParseError::InvalidTag),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn write_joystick_descriptor() {
+        let joystick = Joystick::new(1, JoystickType::XInputGamepad, c"Xbox".into())
+            .with_xbox(true)
+            .with_axes(vec![AxisType::X, AxisType::Y])
+            .with_button_count(10)
+            .with_pov_count(1);
+
+        let mut out = Vec::new();
+        Packet::default()
+            .with_joystick(joystick)
+            .with_game_data(Some(GameData::triple(b'A', b'A', b'A')))
+            .write_bytes(&mut out);
+
+        assert_eq!(
+            out,
+            [
+                0x00, 0x0e, 0x02, 0x01, 0x01, 0x01, 0x04, b'X', b'b', b'o', b'x', 0x02, 0x00, 0x01,
+                0x0a, 0x01, // Joystick descriptor
+                0x00, 0x04, 0x0e, b'A', b'A', b'A', // Game data
+            ]
+        );
+    }
+
+    #[test]
+    fn write_heartbeat() {
+        let mut out = Vec::new();
+        Packet::default().write_bytes(&mut out);
+
+        assert_eq!(out, [0x00, 0x00]);
+    }
+
+    #[test]
+    fn truncate_long_names() {
+        let long = CString::new(vec![b'a'; 300]).unwrap();
+
+        let mut out = Vec::new();
+        MatchInfo::new(Some(long.clone()), MatchType::None).write_bytes(&mut out);
+        assert_eq!(out[0], u8::MAX);
+        assert_eq!(out.len(), 1 + 255 + 1);
+
+        let mut out = Vec::new();
+        Joystick::new(0, JoystickType::Unknown, long).write_bytes(&mut out);
+        assert_eq!(out[3], u8::MAX);
+        assert_eq!(out.len(), 3 + 1 + 255 + 3);
+    }
+
+    #[test]
+    fn decode_split_frames() {
+        let mut out = Vec::new();
+        Packet::default()
+            .with_game_data(Some(GameData::single(b'L')))
+            .write_bytes(&mut out);
+
+        let mut decoder = Decoder::new();
+        decoder.extend(&out[..2]);
+        assert_eq!(Packet::decode(&mut decoder), Ok(None));
+
+        decoder.extend(&out[2..]);
+        let packet = Packet::decode(&mut decoder).unwrap().unwrap();
+        assert_eq!(packet.game_data(), Some(GameData::single(b'L')));
+    }
+
+    #[test]
+    fn decode_past_bad_frame() {
+        let mut out = Vec::new();
+        Packet::default()
+            .with_game_data(Some(GameData::single(b'L')))
+            .write_bytes(&mut out);
+        // Game data longer than three characters
+        out.extend_from_slice(b"\x00\x05\x0eLRLR");
+        Packet::default()
+            .with_game_data(Some(GameData::single(b'R')))
+            .write_bytes(&mut out);
+
+        let mut decoder = Decoder::new();
+        decoder.extend(&out);
+
+        let first = Packet::decode(&mut decoder).unwrap().unwrap();
+        assert_eq!(first.game_data(), Some(GameData::single(b'L')));
+        assert_eq!(
+            Packet::decode(&mut decoder),
+            Err(TcpParseError::InvalidLength)
+        );
+        let second = Packet::decode(&mut decoder).unwrap().unwrap();
+        assert_eq!(second.game_data(), Some(GameData::single(b'R')));
+        assert!(decoder.is_empty());
+    }
+
+    fn name() -> impl Strategy<Value = CString> {
+        proptest::collection::vec(1..=u8::MAX, 0..=usize::from(u8::MAX))
+            .prop_map(|bytes| CString::new(bytes).unwrap())
+    }
+
+    fn joystick() -> impl Strategy<Value = Joystick> {
+        let ty = prop_oneof![
+            Just(JoystickType::Unknown),
+            Just(JoystickType::XInputGamepad),
+            Just(JoystickType::XInputArcadePad),
+            Just(JoystickType::HIDFirstPerson),
+        ];
+        let axis = prop_oneof![
+            Just(AxisType::X),
+            Just(AxisType::Y),
+            Just(AxisType::Z),
+            Just(AxisType::Twist),
+            Just(AxisType::Throttle),
+        ];
+
+        (
+            any::<u8>(),
+            any::<bool>(),
+            ty,
+            name(),
+            proptest::collection::vec(axis, 0..12),
+            any::<u8>(),
+            any::<u8>(),
+        )
+            .prop_map(|(index, is_xbox, ty, name, axes, buttons, povs)| {
+                Joystick::new(index, ty, name)
+                    .with_xbox(is_xbox)
+                    .with_axes(axes)
+                    .with_button_count(buttons)
+                    .with_pov_count(povs)
+            })
+    }
+
+    fn match_info() -> impl Strategy<Value = MatchInfo> {
+        let ty = prop_oneof![
+            Just(MatchType::None),
+            Just(MatchType::Practice),
+            Just(MatchType::Qualifications),
+            Just(MatchType::Eliminations),
+        ];
+
+        (proptest::option::of(name()), ty)
+            .prop_map(|(competition, ty)| MatchInfo::new(competition, ty))
+    }
+
+    fn game_data() -> impl Strategy<Value = GameData> {
+        proptest::collection::vec(any::<u8>(), 0..=3)
+            .prop_map(|chars| GameData::from_bytes(&chars).unwrap())
+    }
+
+    proptest! {
+        #[test]
+        fn encode_decode_identity(
+            joysticks in proptest::collection::vec(joystick(), 0..6),
+            match_info in proptest::option::of(match_info()),
+            game_data in proptest::option::of(game_data()),
+        ) {
+            let packet = Packet::default()
+                .with_joysticks(joysticks)
+                .with_match_info(match_info)
+                .with_game_data(game_data);
+
+            let mut out = Vec::new();
+            packet.write_bytes(&mut out);
+
+            prop_assert_eq!(Packet::try_from(out.as_slice()), Ok(packet));
+        }
+    }
+}
@@ -1,7 +1,17 @@
-use std::ffi::CString;
+use std::{
+    ffi::CString,
+    time::{Duration, SystemTime},
+};
 
-use crate::{traits::Bytes, Alliance, Mode};
+use crate::{
+    joystick::JoystickEvent, recv::udp::UdpParseError, send::tcp::Joystick, timer::MatchTimer,
+    traits::Bytes, Alliance, Mode,
+};
 
+// sequence + comm_version + control + request + alliance
+const HEADER_SIZE: usize = 2 + 1 + 1 + 1 + 1;
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Packet {
     sequence: u16,
     version: u8,
@@ -71,6 +81,49 @@ impl Packet {
         self.tags.append(&mut tags);
         self
     }
+
+    pub fn sequence(&self) -> u16 {
+        self.sequence
+    }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    pub fn control(&self) -> Control {
+        self.ctrl
+    }
+
+    pub fn request(&self) -> Request {
+        self.req
+    }
+
+    pub fn alliance(&self) -> Alliance {
+        self.alliance
+    }
+
+    pub fn tags(&self) -> &[Tag] {
+        &self.tags
+    }
+}
+
+impl TryFrom<&[u8]> for Packet {
+    type Error = UdpParseError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        if value.len() < HEADER_SIZE {
+            return Err(UdpParseError::InvalidLength);
+        }
+
+        Ok(Packet {
+            sequence: u16::from_be_bytes([value[0], value[1]]),
+            version: value[2],
+            ctrl: Control::from_bits(value[3]),
+            req: Request::from_bits(value[4]),
+            alliance: Alliance::from_bits(value[5]).ok_or(UdpParseError::InvalidAlliance)?,
+            tags: Tag::parse_tags(&value[HEADER_SIZE..])?,
+        })
+    }
 }
 
 impl Default for Packet {
@@ -107,13 +160,27 @@ pub enum UdpEvent {
     RebootRoborio,
     RestartCode,
     Alliance(Alliance),
+    /// The state the FMS has the robot in, sent with each of its control packets.
+    ///
+    /// Local changes to the same fields are ignored while the FMS is connected.
+    Field {
+        enabled: bool,
+        mode: Mode,
+        alliance: Alliance,
+    },
     Tag(Tag),
+    MatchTimer(Option<MatchTimer>),
+    /// The timer of a practice match, which takes over from [`UdpEvent::MatchTimer`] until it's cleared with `None`.
+    PracticeTimer(Option<MatchTimer>),
+    /// The time left in the period according to the FMS, counted down from when it's queued.
+    MatchTime(Option<Duration>),
+    Joystick(JoystickEvent),
     TeamNumber(u16),
 }
 
 #[repr(transparent)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-struct Control(u8);
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Control(u8);
 
 impl Bytes for Control {
     fn write_bytes(&self, out: &mut Vec<u8>) {
@@ -127,6 +194,14 @@ impl Control {
     const ESTOP_MASK: u8 = 0x80;
     const FMS_MASK: u8 = 0x08;
 
+    pub fn from_bits(bits: u8) -> Control {
+        Control(bits)
+    }
+
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+
     pub fn mode(&self) -> Mode {
         Mode::from_bits(self.0 & Self::MODE_MASK)
     }
@@ -193,19 +268,22 @@ impl Control {
     }
 }
 
-impl Default for Control {
-    fn default() -> Self {
-        Control(0)
-    }
-}
-
-struct Request(u8);
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Request(u8);
 
 impl Request {
     const REBOOT_ROBORIO_MASK: u8 = 0x08;
     const RESTART_CODE_MASK: u8 = 0x04;
     const DS_MASK: u8 = 0x10;
 
+    pub fn from_bits(bits: u8) -> Request {
+        Request(bits)
+    }
+
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+
     pub fn reboot_roborio(&self) -> bool {
         self.0 & Self::REBOOT_ROBORIO_MASK > 0
     }
@@ -264,13 +342,7 @@ impl Bytes for Request {
     }
 }
 
-impl Default for Request {
-    fn default() -> Self {
-        Request(0)
-    }
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Tag {
     Countdown(f32),
     Joystick {
@@ -292,8 +364,210 @@ pub enum Tag {
     Timezone(CString),
 }
 
+impl Tag {
+    const COUNTDOWN_TAG: u8 = 0x07;
+    const JOYSTICK_TAG: u8 = 0x0c;
+    const DATE_TAG: u8 = 0x0f;
+    const TIMEZONE_TAG: u8 = 0x10;
+
+    const COUNTDOWN_LENGTH: usize = 4;
+    const DATE_LENGTH: usize = 4 + 6;
+
+    const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+    /// The date and time in UTC, to send along with [`Tag::utc`].
+    pub fn date(time: SystemTime) -> Tag {
+        let since_epoch = time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let secs = since_epoch.as_secs();
+        let (year, month, day) = civil_from_days((secs / Self::SECONDS_PER_DAY) as i64);
+        let secs_of_day = secs % Self::SECONDS_PER_DAY;
+
+        Tag::Date {
+            microseconds: since_epoch.subsec_micros(),
+            second: (secs_of_day % 60) as u8,
+            minute: (secs_of_day / 60 % 60) as u8,
+            hour: (secs_of_day / 3600) as u8,
+            day: day as u8,
+            month: (month - 1) as u8,
+            year: (year - 1900) as u8,
+        }
+    }
+
+    /// The timezone that [`Tag::date`] is in.
+    ///
+    /// This is the only zone sent, since the standard library has no way to look up the system's own.
+    /// The roboRIO's clock is still set correctly, but it reports local times in UTC.
+    pub fn utc() -> Tag {
+        Tag::Timezone(c"UTC".into())
+    }
+
+    /// The time a [`Tag::Date`] is for, taking it to be in UTC.
+    ///
+    /// Returns `None` for other tags, or dates before 1970.
+    pub fn time(&self) -> Option<SystemTime> {
+        let Tag::Date {
+            microseconds,
+            second,
+            minute,
+            hour,
+            day,
+            month,
+            year,
+        } = *self
+        else {
+            return None;
+        };
+
+        let days = days_from_civil(year as i64 + 1900, month as i64 + 1, day as i64);
+        let secs = days * Self::SECONDS_PER_DAY as i64
+            + hour as i64 * 3600
+            + minute as i64 * 60
+            + second as i64;
+
+        let since_epoch =
+            Duration::from_secs(secs.try_into().ok()?) + Duration::from_micros(microseconds as u64);
+        Some(SystemTime::UNIX_EPOCH + since_epoch)
+    }
+
+    fn id(&self) -> u8 {
+        match self {
+            Tag::Countdown(_) => Self::COUNTDOWN_TAG,
+            Tag::Joystick { .. } => Self::JOYSTICK_TAG,
+            Tag::Date { .. } => Self::DATE_TAG,
+            Tag::Timezone(_) => Self::TIMEZONE_TAG,
+        }
+    }
+
+    /// Parses the tags following the header of a packet,
+    /// each prefixed with its size and ID.
+    ///
+    /// Tags that are not understood are skipped.
+    pub fn parse_tags(buf: &[u8]) -> Result<Vec<Tag>, UdpParseError> {
+        let mut tags = Vec::new();
+
+        let mut i = 0;
+        while i < buf.len() {
+            let size = buf[i] as usize;
+            i += 1;
+
+            if size > buf.len() - i {
+                return Err(UdpParseError::InvalidLength);
+            }
+
+            if size > 0 {
+                if let Some(tag) = Tag::parse(buf[i], &buf[(i + 1)..(i + size)])? {
+                    tags.push(tag);
+                }
+            }
+            i += size;
+        }
+
+        Ok(tags)
+    }
+
+    fn parse(id: u8, data: &[u8]) -> Result<Option<Tag>, UdpParseError> {
+        let tag = match id {
+            Self::COUNTDOWN_TAG => {
+                if data.len() != Self::COUNTDOWN_LENGTH {
+                    return Err(UdpParseError::InvalidLength);
+                }
+
+                Tag::Countdown(f32::from_be_bytes([data[0], data[1], data[2], data[3]]))
+            }
+            Self::JOYSTICK_TAG => {
+                let mut i = 0;
+                let mut next = |len: usize| {
+                    let bytes = data.get(i..(i + len)).ok_or(UdpParseError::InvalidLength);
+                    i += len;
+                    bytes
+                };
+
+                let axis_count = next(1)?[0] as usize;
+                let axes = next(axis_count)?.iter().map(|axis| *axis as i8).collect();
+
+                let button_count = next(1)?[0];
+                let button_bytes = next((button_count as usize).div_ceil(8))?;
+                let buttons = Buttons::from_bytes(button_count, button_bytes);
+
+                let pov_count = next(1)?[0] as usize;
+                let povs = next(pov_count * 2)?
+                    .chunks_exact(2)
+                    .map(|pov| i16::from_be_bytes([pov[0], pov[1]]))
+                    .collect();
+
+                if i != data.len() {
+                    return Err(UdpParseError::InvalidLength);
+                }
+
+                Tag::Joystick {
+                    axes,
+                    buttons,
+                    povs,
+                }
+            }
+            Self::DATE_TAG => {
+                if data.len() != Self::DATE_LENGTH {
+                    return Err(UdpParseError::InvalidLength);
+                }
+
+                Tag::Date {
+                    microseconds: u32::from_be_bytes([data[0], data[1], data[2], data[3]]),
+                    second: data[4],
+                    minute: data[5],
+                    hour: data[6],
+                    day: data[7],
+                    month: data[8],
+                    year: data[9],
+                }
+            }
+            Self::TIMEZONE_TAG => {
+                Tag::Timezone(CString::new(data).map_err(|_| UdpParseError::InvalidTag)?)
+            }
+            _ => return Ok(None),
+        };
+
+        Ok(Some(tag))
+    }
+}
+
+/// Converts days since the Unix epoch to a year, month and day, with months starting at `1`.
+///
+/// See <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    (year, month, day)
+}
+
+/// Converts a year, month and day to days since the Unix epoch, the inverse of [`civil_from_days`].
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146097 + doe - 719468
+}
+
 impl Bytes for Tag {
+    /// Writes the tag prefixed with its size and ID.
+    /// The size includes the ID but not itself.
     fn write_bytes(&self, out: &mut Vec<u8>) {
+        let start = out.len();
+        out.extend_from_slice(&[0x00, self.id()]);
+
         match self {
             Tag::Countdown(count) => out.extend(count.to_be_bytes()),
             Tag::Joystick {
@@ -301,18 +575,17 @@ impl Bytes for Tag {
                 buttons,
                 povs,
             } => {
+                // Capped so the tag's size can't overflow
+                let axes = &axes[..axes.len().min(Joystick::MAX_AXES as usize)];
                 out.push(axes.len() as u8);
-                let unsigned_axes = axes
-                    .iter()
-                    .map(|signed| unsafe { std::mem::transmute::<i8, u8>(*signed) });
-
-                out.extend(unsigned_axes);
+                out.extend(axes.iter().map(|signed| *signed as u8));
 
                 out.push(buttons.len());
                 buttons.write_bytes(out);
 
+                let povs = &povs[..povs.len().min(Joystick::MAX_POVS as usize)];
                 out.push(povs.len() as u8);
-                let pov_bytes = povs.iter().map(|pov| pov.to_be_bytes()).flatten();
+                let pov_bytes = povs.iter().flat_map(|pov| pov.to_be_bytes());
 
                 out.extend(pov_bytes);
             }
@@ -335,29 +608,62 @@ impl Bytes for Tag {
             }
             Tag::Timezone(timezone) => out.extend_from_slice(timezone.as_bytes()),
         }
+
+        out[start] = (out.len() - start - 1) as u8;
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Buttons {
     count: u8,
     inner: u64,
 }
 
 impl Buttons {
+    /// The most buttons that fit in the bitfield.
+    pub const MAX_COUNT: u8 = u64::BITS as u8;
+
+    /// Holds `count` released buttons, up to [`Buttons::MAX_COUNT`].
     pub fn new(count: u8) -> Buttons {
-        Buttons { count, inner: 0 }
+        Buttons {
+            count: count.min(Self::MAX_COUNT),
+            inner: 0,
+        }
+    }
+
+    /// Reads the buttons from their big-endian bitfield,
+    /// where the first button is the lowest bit of the last byte.
+    ///
+    /// Buttons past [`Buttons::MAX_COUNT`] are dropped.
+    fn from_bytes(count: u8, bytes: &[u8]) -> Buttons {
+        let inner = bytes
+            .iter()
+            .fold(0u64, |inner, byte| (inner << 8) | *byte as u64);
+
+        Buttons {
+            count: count.min(Self::MAX_COUNT),
+            inner,
+        }
     }
 
     pub fn len(&self) -> u8 {
         self.count
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Gets the state of the `n`th button, starting at `0`.
+    pub fn button(&self, n: u8) -> bool {
+        n < self.count && (self.inner & (1 << n)) > 0
+    }
+
     /// Sets the `n`th button to the given `state`.
     ///
     /// # Panics
     ///
-    /// This function will panic if `n` is greater than the `count` given when calling [`Buttons::new`].
+    /// This function will panic if `n` is greater than or equal to `64`.
     pub fn set_button(&mut self, n: u8, state: bool) {
         if state {
             self.inner |= 1 << n;
@@ -369,8 +675,59 @@ impl Buttons {
 
 impl Bytes for Buttons {
     fn write_bytes(&self, out: &mut Vec<u8>) {
-        let bytes = (self.count / 8).max(1);
+        // The first button is the lowest bit of the last byte
+        let bytes = (self.count as usize).div_ceil(8);
+
+        out.extend_from_slice(&self.inner.to_be_bytes()[(8 - bytes)..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_control_packet() {
+        let mut buttons = Buttons::new(10);
+        buttons.set_button(0, true);
+        buttons.set_button(9, true);
+
+        let packet = Packet::default()
+            .with_sequence(0x1234)
+            .with_enabled(true)
+            .with_mode(Mode::Autonomous)
+            .with_ds_connected(true)
+            .with_alliance(Alliance::Blue2)
+            .with_tag(Tag::Countdown(12.5))
+            .with_tag(Tag::Joystick {
+                axes: vec![-128, 0, 127],
+                buttons,
+                povs: vec![-1, 90],
+            })
+            .with_tag(Tag::utc());
+
+        let mut out = Vec::new();
+        packet.write_bytes(&mut out);
+
+        assert_eq!(Packet::try_from(out.as_slice()), Ok(packet));
+    }
+
+    #[test]
+    fn write_date() {
+        let time = SystemTime::UNIX_EPOCH + Duration::new(1709214330, 250_000_000);
+
+        let mut out = Vec::new();
+        Tag::date(time).write_bytes(&mut out);
+        Tag::utc().write_bytes(&mut out);
+        assert_eq!(Tag::date(time).time(), Some(time));
 
-        out.extend_from_slice(&self.inner.to_be_bytes()[0..(bytes as usize)])
+        assert_eq!(
+            out,
+            [
+                0x0b, 0x0f, 0x00, 0x03, 0xd0, 0x90, 30, 45, 13, 29, 1,
+                124, // 2024-02-29 13:45:30.25
+                0x04, 0x10, b'U', b'T', b'C', // Timezone
+            ]
+        );
     }
 }
@@ -16,6 +16,7 @@ impl Runtime {
         }
     }
 
+    #[cfg(feature = "sync")]
     pub fn block_on<F: Future>(&self, future: F) -> F::Output {
         match self {
             Runtime::Runtime(rt) => rt.block_on(future),
@@ -0,0 +1,27 @@
+//! Helpers shared by the tests that talk over real sockets.
+
+use std::{future::Future, time::Duration};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Waits on the future, failing the test if it takes too long.
+pub(crate) async fn within<F: Future>(future: F) -> F::Output {
+    tokio::time::timeout(TIMEOUT, future)
+        .await
+        .expect("timed out")
+}
+
+/// Waits until the condition holds, checking every few milliseconds,
+/// and fails the test if it never does.
+pub(crate) async fn until<F, Fut>(mut condition: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = bool>,
+{
+    within(async {
+        while !condition().await {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+}
@@ -0,0 +1,177 @@
+//! Counting down each period of a match, like the FMS does.
+
+use std::time::{Duration, Instant};
+
+use crate::Mode;
+
+pub(crate) const AUTO: Duration = Duration::from_secs(15);
+pub(crate) const TELEOP: Duration = Duration::from_secs(135);
+
+/// How long each period of a match lasts, set with [`Robot::set_match_timer`](crate::Robot::set_match_timer).
+///
+/// A period starts counting down when the robot is enabled in its mode,
+/// and the time left is sent to the robot every control packet,
+/// so `Timer.getMatchTime()` works the same as on the field.
+/// Test mode isn't timed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MatchTimer {
+    auto: Duration,
+    teleop: Duration,
+}
+
+impl Default for MatchTimer {
+    fn default() -> Self {
+        MatchTimer {
+            auto: AUTO,
+            teleop: TELEOP,
+        }
+    }
+}
+
+impl MatchTimer {
+    pub fn new(auto: Duration, teleop: Duration) -> Self {
+        MatchTimer { auto, teleop }
+    }
+
+    pub fn with_auto(mut self, auto: Duration) -> Self {
+        self.auto = auto;
+        self
+    }
+
+    pub fn with_teleop(mut self, teleop: Duration) -> Self {
+        self.teleop = teleop;
+        self
+    }
+
+    /// How long the period for `mode` lasts, or `None` if it isn't timed.
+    pub fn duration(&self, mode: Mode) -> Option<Duration> {
+        match mode {
+            Mode::Autonomous => Some(self.auto),
+            Mode::Teleoperated => Some(self.teleop),
+            Mode::Test => None,
+        }
+    }
+}
+
+/// The period being counted down, owned by the UDP thread.
+#[derive(Debug, Default)]
+pub(crate) struct Countdown {
+    timer: Option<MatchTimer>,
+    // The timer of a running practice match, which takes over from the user's timer
+    practice: Option<MatchTimer>,
+    period: Option<(Mode, Instant)>,
+    // The end of the period the FMS is counting down, which takes over from the timer
+    fms_end: Option<Instant>,
+}
+
+impl Countdown {
+    pub(crate) fn new(timer: Option<MatchTimer>) -> Countdown {
+        Countdown {
+            timer,
+            practice: None,
+            period: None,
+            fms_end: None,
+        }
+    }
+
+    pub(crate) fn set_timer(&mut self, timer: Option<MatchTimer>) {
+        self.timer = timer;
+        self.period = None;
+    }
+
+    /// Counts down a practice match, or goes back to the user's timer with `None`.
+    pub(crate) fn set_practice_timer(&mut self, timer: Option<MatchTimer>) {
+        self.practice = timer;
+        self.period = None;
+    }
+
+    /// Counts down to when the FMS says the period ends, or goes back to the timer with `None`.
+    pub(crate) fn set_fms_end(&mut self, end: Option<Instant>) {
+        self.fms_end = end;
+        self.period = None;
+    }
+
+    /// The time left in the current period,
+    /// starting a new one when the robot is enabled or changes mode.
+    pub(crate) fn update(&mut self, enabled: bool, mode: Mode, now: Instant) -> Option<Duration> {
+        if let Some(end) = self.fms_end {
+            return enabled.then(|| end.saturating_duration_since(now));
+        }
+
+        let timer = match self.practice.or(self.timer) {
+            Some(timer) if enabled => timer,
+            _ => {
+                self.period = None;
+                return None;
+            }
+        };
+
+        let end = match self.period {
+            Some((period, end)) if period == mode => end,
+            _ => {
+                self.period = None;
+                let end = now + timer.duration(mode)?;
+                self.period = Some((mode, end));
+                end
+            }
+        };
+
+        Some(end.saturating_duration_since(now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn count_down_periods() {
+        let timer = MatchTimer::default().with_auto(Duration::from_secs(10));
+        let mut countdown = Countdown::new(Some(timer));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert_eq!(countdown.update(false, Mode::Autonomous, at(0)), None);
+        assert_eq!(
+            countdown.update(true, Mode::Autonomous, at(1)),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(
+            countdown.update(true, Mode::Autonomous, at(5)),
+            Some(Duration::from_secs(6))
+        );
+        assert_eq!(
+            countdown.update(true, Mode::Autonomous, at(20)),
+            Some(Duration::ZERO)
+        );
+
+        assert_eq!(
+            countdown.update(true, Mode::Teleoperated, at(21)),
+            Some(Duration::from_secs(135))
+        );
+        assert_eq!(countdown.update(true, Mode::Test, at(22)), None);
+
+        countdown.set_fms_end(Some(at(30)));
+        assert_eq!(
+            countdown.update(true, Mode::Teleoperated, at(23)),
+            Some(Duration::from_secs(7))
+        );
+        countdown.set_fms_end(None);
+
+        countdown.set_practice_timer(Some(
+            MatchTimer::default().with_teleop(Duration::from_secs(5)),
+        ));
+        assert_eq!(
+            countdown.update(true, Mode::Teleoperated, at(23)),
+            Some(Duration::from_secs(5))
+        );
+        countdown.set_practice_timer(None);
+        assert_eq!(
+            countdown.update(true, Mode::Teleoperated, at(23)),
+            Some(Duration::from_secs(135))
+        );
+
+        countdown.set_timer(None);
+        assert_eq!(countdown.update(true, Mode::Teleoperated, at(23)), None);
+    }
+}
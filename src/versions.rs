@@ -0,0 +1,109 @@
+//! The software and firmware versions the roboRIO reports, like the versions in the official driver station.
+
+use crate::recv::tcp::{Device, Tag};
+
+/// The latest version of every device the roboRIO has reported, from [`Robot::versions`](crate::Robot::versions).
+///
+/// The roboRIO reports versions when the TCP connection opens,
+/// so [`Robot::request_versions`](crate::Robot::request_versions) reconnects to refresh them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Versions {
+    versions: Vec<Version>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    pub device: Device,
+    /// The device's ID on the CAN bus, or which piece of software it is.
+    pub id: u8,
+    pub name: String,
+    pub version: String,
+}
+
+impl Versions {
+    /// Every reported version, in the order they were first received.
+    pub fn iter(&self) -> impl Iterator<Item = &Version> {
+        self.versions.iter()
+    }
+
+    pub fn get(&self, device: Device, id: u8) -> Option<&Version> {
+        self.versions
+            .iter()
+            .find(|version| version.device == device && version.id == id)
+    }
+
+    /// Finds a version by name, such as `roboRIO Image` or `FRC Netcomm`.
+    pub fn find(&self, name: &str) -> Option<&Version> {
+        self.versions.iter().find(|version| version.name == name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.versions.is_empty()
+    }
+
+    /// Keeps the version from the tag, if it has one.
+    pub(crate) fn apply(&mut self, tag: &Tag) {
+        let Tag::VersionInfo {
+            ty,
+            id,
+            name,
+            version,
+        } = tag
+        else {
+            return;
+        };
+
+        let version = Version {
+            device: *ty,
+            id: *id,
+            name: name.to_string_lossy().into_owned(),
+            version: version.to_string_lossy().into_owned(),
+        };
+
+        // Software shares an ID, so it is told apart by name
+        let existing = self.versions.iter_mut().find(|existing| {
+            existing.device == version.device
+                && existing.id == version.id
+                && (version.device != Device::Software || existing.name == version.name)
+        });
+
+        match existing {
+            Some(existing) => *existing = version,
+            None => self.versions.push(version),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+
+    use super::*;
+
+    fn tag(ty: Device, id: u8, name: &str, version: &str) -> Tag {
+        Tag::VersionInfo {
+            ty,
+            id,
+            name: CString::new(name).unwrap(),
+            version: CString::new(version).unwrap(),
+        }
+    }
+
+    #[test]
+    fn catalog_versions() {
+        let mut versions = Versions::default();
+
+        versions.apply(&tag(Device::Software, 0, "roboRIO Image", "2024_v2.1"));
+        versions.apply(&tag(Device::Software, 0, "FRC Netcomm", "24.0.0f1"));
+        versions.apply(&tag(Device::PDP, 0, "PDP", "1.40"));
+        versions.apply(&tag(Device::CANTalon, 3, "Talon SRX", "22.0"));
+        versions.apply(&tag(Device::CANTalon, 3, "Talon SRX", "22.1"));
+        versions.apply(&tag(Device::Software, 0, "roboRIO Image", "2024_v2.2"));
+
+        assert_eq!(versions.iter().count(), 4);
+        assert_eq!(versions.find("roboRIO Image").unwrap().version, "2024_v2.2");
+        assert_eq!(versions.find("FRC Netcomm").unwrap().version, "24.0.0f1");
+        assert_eq!(versions.get(Device::CANTalon, 3).unwrap().version, "22.1");
+        assert_eq!(versions.get(Device::PCM, 0), None);
+    }
+}
@@ -0,0 +1,118 @@
+//! Regression tests against the traffic captured in `netlogs/`.
+//!
+//! These need the `pcap` feature: `cargo test --features pcap`.
+
+use std::net::SocketAddrV4;
+
+use driverstation::{
+    pcap::{self, Segment},
+    recv::{
+        tcp,
+        udp::{self, UdpResponse},
+    },
+    send::udp::Packet,
+    traits::Bytes,
+};
+
+const READ_CONN: &[u8] = include_bytes!("../netlogs/read_conn.pcapng");
+
+const ROBOT_UDP_PORT: u16 = 1110;
+const ROBOT_TCP_PORT: u16 = 1740;
+const DS_UDP_RX_PORT: u16 = 1150;
+
+fn segments() -> Vec<Segment> {
+    pcap::read(READ_CONN).expect("unable to read capture")
+}
+
+/// The two ends of the TCP connection, as `(driver station, robot)`.
+fn tcp_ends(segments: &[Segment]) -> (SocketAddrV4, SocketAddrV4) {
+    let segment = segments
+        .iter()
+        .find(|segment| segment.is_tcp() && segment.dst.port() == ROBOT_TCP_PORT)
+        .expect("no TCP traffic to the robot");
+
+    (segment.src, segment.dst)
+}
+
+#[test]
+fn parse_robot_udp() {
+    let responses: Vec<_> = segments()
+        .into_iter()
+        .filter(|segment| segment.is_udp() && segment.dst.port() == DS_UDP_RX_PORT)
+        .collect();
+    assert!(!responses.is_empty());
+
+    let mut tags = 0;
+    for segment in responses {
+        let response = UdpResponse::try_from(segment.payload.as_slice())
+            .unwrap_or_else(|err| panic!("{err:?} parsing {:02x?}", segment.payload));
+        tags += response.tags.len();
+
+        for tag in response.tags {
+            if let udp::Tag::CPUInfo { num_cpus, usage } = tag {
+                assert_eq!(num_cpus, 2);
+                assert!(usage.iter().all(|cpu| (0.0..=100.0).contains(&cpu.total())));
+            }
+        }
+    }
+
+    assert!(tags > 0, "no tags were parsed out of the capture");
+}
+
+#[test]
+fn parse_ds_udp() {
+    let packets: Vec<_> = segments()
+        .into_iter()
+        .filter(|segment| segment.is_udp() && segment.dst.port() == ROBOT_UDP_PORT)
+        .collect();
+    assert!(!packets.is_empty());
+
+    for segment in packets {
+        let packet = Packet::try_from(segment.payload.as_slice())
+            .unwrap_or_else(|err| panic!("{err:?} parsing {:02x?}", segment.payload));
+
+        // Every part of the official driver station's packets should be understood
+        let mut out = Vec::new();
+        packet.write_bytes(&mut out);
+        assert_eq!(out, segment.payload, "re-encoding {packet:?}");
+    }
+}
+
+#[test]
+fn decode_robot_tcp() {
+    let segments = segments();
+    let (ds, robot) = tcp_ends(&segments);
+    let stream = pcap::tcp_stream(&segments, robot, ds);
+
+    let mut decoder = tcp::Decoder::new();
+    decoder.extend(&stream);
+
+    let mut tags = Vec::new();
+    while let Some(response) = decoder.decode().expect("unable to decode TCP stream") {
+        tags.extend(response.tags);
+    }
+    assert!(decoder.is_empty(), "capture ended partway through a frame");
+
+    let count = |f: fn(&tcp::Tag) -> bool| tags.iter().filter(|tag| f(tag)).count();
+    assert!(count(|tag| matches!(tag, tcp::Tag::StandardOutput { .. })) > 0);
+    assert!(count(|tag| matches!(tag, tcp::Tag::ErrorMessage { .. })) > 0);
+    assert!(count(|tag| matches!(tag, tcp::Tag::VersionInfo { .. })) > 0);
+}
+
+#[test]
+fn frame_ds_tcp() {
+    let segments = segments();
+    let (ds, robot) = tcp_ends(&segments);
+    let stream = pcap::tcp_stream(&segments, ds, robot);
+
+    let mut decoder = tcp::Decoder::new();
+    decoder.extend(&stream);
+
+    let mut frames = 0;
+    while decoder.next_frame().is_some() {
+        frames += 1;
+    }
+
+    assert!(frames > 0);
+    assert!(decoder.is_empty(), "capture ended partway through a frame");
+}